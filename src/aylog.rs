@@ -0,0 +1,133 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+const ENVELOPE_SHAPE: usize = 13;
+
+// Every AY register write, one list per 50Hz frame, for saving as .psg or
+// .ym music files
+#[derive(Clone, RustcEncodable, RustcDecodable)]
+pub struct AyLog {
+    clock_hz: u32,
+    frames: Vec<Vec<(u8, u8)>>,
+}
+
+impl AyLog {
+    pub fn new(clock_hz: u32) -> Self {
+        AyLog {
+            clock_hz,
+            frames: vec![Vec::new()],
+        }
+    }
+
+    pub fn record(&mut self, register: u8, val: u8) {
+        self.frames.last_mut().unwrap().push((register, val));
+    }
+
+    pub fn end_frame(&mut self) {
+        self.frames.push(Vec::new());
+    }
+
+    // Writes of the finished frames
+    pub fn frames(&self) -> &[Vec<(u8, u8)>] {
+        &self.frames[..self.frames.len() - 1]
+    }
+
+    // PSG: a 16 byte header, then register and value pairs, 0xFF at the
+    // end of each frame and 0xFE n for 4n frames without any writes
+    pub fn write_psg<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let mut header = [0u8; 16];
+        header[..4].copy_from_slice(b"PSG\x1A");
+        out.write_all(&header)?;
+
+        let mut empty = 0;
+        for frame in self.frames() {
+            if frame.is_empty() {
+                empty += 1;
+                continue;
+            }
+            write_psg_empty_frames(out, empty)?;
+            empty = 0;
+
+            for &(register, val) in frame {
+                out.write_all(&[register, val])?;
+            }
+            out.write_all(&[0xFF])?;
+        }
+        write_psg_empty_frames(out, empty)?;
+        out.write_all(&[0xFD])
+    }
+
+    // YM5 or YM6, uncompressed. Each frame holds the 16 registers as they
+    // stand at its end, with R13 at 0xFF unless it was written, since
+    // writing it restarts the envelope. Without any special effects the two
+    // versions only differ in the header.
+    pub fn write_ym<W: Write>(&self, out: &mut W, version: u8) -> io::Result<()> {
+        assert!(version == 5 || version == 6, "Unsupported YM version: {}", version);
+        let frames = self.frames();
+
+        out.write_all(format!("YM{}!LeOnArD!", version).as_bytes())?;
+        out.write_all(&be_u32(frames.len() as u32))?;
+        // Interleaved data
+        out.write_all(&be_u32(0x01))?;
+        // No digidrums
+        out.write_all(&[0, 0])?;
+        out.write_all(&be_u32(self.clock_hz))?;
+        out.write_all(&[0, 50])?;
+        // Loop frame and size of additional data
+        out.write_all(&be_u32(0))?;
+        out.write_all(&[0, 0])?;
+        // Song name, author and comment
+        out.write_all(b"\0\0Saved by rustz80emu\0")?;
+
+        let mut registers = [0u8; 16];
+        let mut states = Vec::with_capacity(frames.len());
+        for frame in frames {
+            let mut state = registers;
+            state[ENVELOPE_SHAPE] = 0xFF;
+            for &(register, val) in frame {
+                registers[register as usize] = val;
+                state[register as usize] = val;
+            }
+            states.push(state);
+        }
+        for register in 0..16 {
+            for state in &states {
+                out.write_all(&[state[register]])?;
+            }
+        }
+
+        out.write_all(b"End!")
+    }
+
+    // The format follows the extension: .psg, anything else is YM6
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let is_psg = path.as_ref().extension()
+            .and_then(|ext| ext.to_str())
+            .map_or(false, |ext| ext.eq_ignore_ascii_case("psg"));
+        let mut out = BufWriter::new(File::create(path)?);
+        if is_psg {
+            self.write_psg(&mut out)
+        } else {
+            self.write_ym(&mut out, 6)
+        }
+    }
+}
+
+fn write_psg_empty_frames<W: Write>(out: &mut W, frames: usize) -> io::Result<()> {
+    let mut frames = frames;
+    while frames >= 4 {
+        let n = if frames / 4 > 0xFF { 0xFF } else { frames / 4 };
+        out.write_all(&[0xFE, n as u8])?;
+        frames -= n * 4;
+    }
+    for _ in 0..frames {
+        out.write_all(&[0xFF])?;
+    }
+    Ok(())
+}
+
+fn be_u32(val: u32) -> [u8; 4] {
+    [(val >> 24) as u8, (val >> 16) as u8, (val >> 8) as u8, val as u8]
+}
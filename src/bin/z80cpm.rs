@@ -0,0 +1,45 @@
+use std::path::Path;
+
+extern crate z80emulib;
+use z80emulib::cpm::*;
+use z80emulib::utils::read_bin;
+
+extern crate getopts;
+use getopts::{Options, ParsingStyle};
+use std::env;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let mut opts = Options::new();
+    // Everything after the program name belongs to the CP/M program
+    opts.parsing_style(ParsingStyle::StopAtFirstFree);
+
+    opts.optflag(
+        "h",
+        "help",
+        "Print this help menu (all other options are ignored)");
+    opts.optopt(
+        "r",
+        "root",
+        "Host directory that the CP/M drives map to (defaults to the current directory)",
+        "PATH");
+
+    let matches = match opts.parse(&args[1..]) {
+        Ok(m) => { m }
+        Err(f) => { panic!(f.to_string()) }
+    };
+
+    if matches.opt_present("h") || matches.free.is_empty() {
+        let brief = format!("Usage: {} [options] PROGRAM.COM [ARGS...]", &args[0]);
+        print!("{}", opts.usage(&brief));
+        return;
+    }
+
+    let root = matches.opt_str("r").unwrap_or_else(|| String::from("."));
+
+    let program = read_bin(Path::new(&matches.free[0]));
+    let mut machine = CpmMachine::new(&program, root);
+    machine.set_args(&matches.free[1..]);
+
+    machine.run();
+}
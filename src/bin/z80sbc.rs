@@ -0,0 +1,52 @@
+use std::path::Path;
+
+extern crate z80emulib;
+use z80emulib::sbc::*;
+use z80emulib::utils::read_bin;
+
+extern crate getopts;
+use getopts::Options;
+use std::env;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let mut opts = Options::new();
+
+    opts.optflag(
+        "d",
+        "debug",
+        "Start the emulator with debugger on, break on first instruction");
+    opts.optflag(
+        "h",
+        "help",
+        "Print this help menu (all other options are ignored)");
+    opts.optopt(
+        "p",
+        "rom-page",
+        "Select an 8K page of a multi-ROM image (defaults to 0)",
+        "PAGE");
+
+    let matches = match opts.parse(&args[1..]) {
+        Ok(m) => { m }
+        Err(f) => { panic!(f.to_string()) }
+    };
+
+    if matches.opt_present("h") || matches.free.is_empty() {
+        let brief = format!("Usage: {} [options] ROM", &args[0]);
+        print!("{}", opts.usage(&brief));
+        return;
+    }
+
+    let rom_page = match matches.opt_str("p") {
+        Some(page) => page.parse().unwrap(),
+        None => 0,
+    };
+
+    let rom = read_bin(Path::new(&matches.free[0]));
+    let sbc = SbcBuilder::new(rom)
+        .rom_page(rom_page)
+        .debug(matches.opt_present("d"))
+        .finalize();
+
+    sbc.run();
+}
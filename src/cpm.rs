@@ -0,0 +1,652 @@
+use ::interconnect::*;
+use ::peripherals::*;
+use ::cpu::*;
+
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Read, Write, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use std::rc::Rc;
+use std::cell::RefCell;
+
+// Zero page and TPA layout of a CP/M 2.2 system
+const WBOOT_JUMP: u16 = 0x0000;
+const BDOS_JUMP: u16 = 0x0005;
+const FCB1: u16 = 0x005C;
+const FCB2: u16 = 0x006C;
+const DEFAULT_DMA: u16 = 0x0080;
+const TPA: u16 = 0x0100;
+
+// The BDOS and BIOS only exist as traps; each entry point holds a RET that
+// is executed once the call has been emulated
+const BDOS: u16 = 0xFE00;
+const BIOS: u16 = 0xFF00;
+const BIOS_ENTRIES: u16 = 17;
+
+const RECORD_SIZE: usize = 128;
+const RECORDS_PER_EXTENT: u64 = 128;
+const EOF_MARKER: u8 = 0x1A;
+
+pub struct CpmMachine {
+    cpu: Cpu,
+    memory: Rc<RefCell<Memory>>,
+
+    // Host directory that every CP/M drive maps to
+    root: PathBuf,
+
+    dma: u16,
+
+    // Remaining matches of the last "search first" call
+    search_results: Vec<[u8; 11]>,
+
+    console_in: Box<dyn Read>,
+    console_out: Box<dyn Write>,
+}
+
+impl CpmMachine {
+    pub fn new<P: AsRef<Path>>(program: &[u8], root: P) -> Self {
+        assert!(program.len() <= (BDOS - TPA) as usize,
+                "program does not fit in the TPA");
+
+        let memory = Rc::new(RefCell::new(MemoryBuilder::new()
+            .writable_rom(true)
+            .finalize()));
+
+        {
+            let mut memory = memory.borrow_mut();
+
+            // JP WBOOT and JP BDOS; IOBYTE and the current drive stay at 0
+            for &(addr, target) in [(WBOOT_JUMP, BIOS + 3), (BDOS_JUMP, BDOS)].iter() {
+                memory.write_word(addr, 0xC3);
+                memory.write_word(addr + 1, target as u8);
+                memory.write_word(addr + 2, (target >> 8) as u8);
+            }
+
+            memory.write_word(BDOS, 0xC9);
+            for entry in 0..BIOS_ENTRIES {
+                memory.write_word(BIOS + entry * 3, 0xC9);
+            }
+
+            for (i, byte) in program.iter().enumerate() {
+                memory.write_word(TPA + i as u16, *byte);
+            }
+        }
+
+        let interconnect = Interconnect::without_peripherals(memory.clone());
+
+        let mut cpu = Cpu::new(interconnect);
+        cpu.set_pc(TPA);
+
+        // A RET from the program returns to the warm boot vector
+        memory.borrow_mut().write_word(BDOS - 1, 0x00);
+        memory.borrow_mut().write_word(BDOS - 2, 0x00);
+        cpu.write_reg16(Reg16::SP, BDOS - 2);
+
+        let mut machine = CpmMachine {
+            cpu,
+            memory,
+            root: root.as_ref().to_path_buf(),
+            dma: DEFAULT_DMA,
+            search_results: Vec::new(),
+            console_in: Box::new(io::stdin()),
+            console_out: Box::new(io::stdout()),
+        };
+        machine.set_args::<&str>(&[]);
+        machine
+    }
+
+    pub fn set_console(&mut self, input: Box<dyn Read>, output: Box<dyn Write>) {
+        self.console_in = input;
+        self.console_out = output;
+    }
+
+    // Fill in the command tail and the two default FCBs the way the CCP does
+    pub fn set_args<S: AsRef<str>>(&mut self, args: &[S]) {
+        let mut tail = String::new();
+        for arg in args {
+            tail.push(' ');
+            tail.push_str(&arg.as_ref().to_uppercase());
+        }
+        tail.truncate(RECORD_SIZE - 1);
+
+        self.poke(DEFAULT_DMA, tail.len() as u8);
+        for (i, byte) in tail.bytes().enumerate() {
+            self.poke(DEFAULT_DMA + 1 + i as u16, byte);
+        }
+        self.poke(DEFAULT_DMA + 1 + tail.len() as u16, 0x00);
+
+        for &(fcb, index) in [(FCB1, 0), (FCB2, 1)].iter() {
+            let (drive, name) = match args.get(index) {
+                Some(arg) => parse_filename(arg.as_ref()),
+                None => (0, [b' '; 11]),
+            };
+            self.poke(fcb, drive);
+            self.write_fcb_name(fcb, &name);
+            for offset in 12..16 {
+                self.poke(fcb + offset, 0x00);
+            }
+        }
+        self.poke(FCB1 + 32, 0x00);
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    pub fn run(&mut self) {
+        loop {
+            let keep_running = match self.cpu.get_pc() {
+                WBOOT_JUMP => false,
+                BDOS => self.bdos(),
+                pc if pc >= BIOS && pc < BIOS + BIOS_ENTRIES * 3 && (pc - BIOS) % 3 == 0 => {
+                    self.bios(((pc - BIOS) / 3) as u8)
+                }
+                _ => true,
+            };
+            if !keep_running { break; }
+
+            self.cpu.run_instruction();
+
+            // There is no frame to synchronise to, so keep the counter bounded
+            self.cpu.tcycles = 0;
+        }
+
+        self.console_out.flush().unwrap();
+    }
+
+    fn bios(&mut self, function: u8) -> bool {
+        match function {
+            // BOOT, WBOOT
+            0 | 1 => return false,
+            // CONST
+            2 => self.cpu.write_reg8(Reg8::A, 0x00),
+            // CONIN
+            3 => {
+                let c = self.console_read();
+                self.cpu.write_reg8(Reg8::A, c);
+            }
+            // CONOUT
+            4 => {
+                let c = self.cpu.read_reg8(Reg8::C);
+                self.console_write(c);
+            }
+            // READER
+            7 => self.cpu.write_reg8(Reg8::A, EOF_MARKER),
+            // LIST, PUNCH and the disk functions, which are only
+            // supported through the BDOS
+            _ => self.cpu.write_reg8(Reg8::A, 0x00),
+        }
+        true
+    }
+
+    fn bdos(&mut self) -> bool {
+        let function = self.cpu.read_reg8(Reg8::C);
+        let e = self.cpu.read_reg8(Reg8::E);
+        let de = self.cpu.read_reg16(Reg16::DE);
+
+        match function {
+            // System reset
+            0 => return false,
+            // Console input
+            1 => {
+                let c = self.console_read();
+                self.console_write(c);
+                self.ret8(c);
+            }
+            // Console output
+            2 => self.console_write(e),
+            // Direct console I/O
+            6 => {
+                match e {
+                    0xFF => {
+                        let c = self.console_read();
+                        self.ret8(c);
+                    }
+                    0xFE => self.ret8(0x00),
+                    _ => self.console_write(e),
+                }
+            }
+            // Print string
+            9 => {
+                let mut addr = de;
+                loop {
+                    let c = self.peek(addr);
+                    if c == b'$' { break; }
+                    self.console_write(c);
+                    addr = addr.wrapping_add(1);
+                }
+            }
+            // Read console buffer
+            10 => self.read_console_buffer(de),
+            // Get console status; input is always read blocking, so never
+            // report a pending character
+            11 => self.ret8(0x00),
+            // Return version number
+            12 => self.ret16(0x0022),
+            // Reset disk system
+            13 => {
+                self.dma = DEFAULT_DMA;
+                self.ret8(0x00);
+            }
+            // Select disk
+            14 => self.ret8(0x00),
+            // Open file
+            15 => {
+                let result = match self.host_path(de) {
+                    Some(path) => {
+                        self.poke(de + 14, 0x00);
+                        let record = self.fcb_record(de);
+                        self.update_record_count(de, &path, record);
+                        0x00
+                    }
+                    None => 0xFF,
+                };
+                self.ret8(result);
+            }
+            // Close file
+            16 => {
+                let result = if self.host_path(de).is_some() { 0x00 } else { 0xFF };
+                self.ret8(result);
+            }
+            // Search for first
+            17 => {
+                let pattern = self.read_fcb_name(de);
+                let mut matches: Vec<[u8; 11]> = self.directory()
+                    .into_iter()
+                    .map(|(_, name)| name)
+                    .filter(|name| name_matches(&pattern, name))
+                    .collect();
+                matches.reverse();
+                self.search_results = matches;
+                self.search_next();
+            }
+            // Search for next
+            18 => self.search_next(),
+            // Delete file
+            19 => {
+                let pattern = self.read_fcb_name(de);
+                let mut result = 0xFF;
+                for (path, name) in self.directory() {
+                    if name_matches(&pattern, &name) && fs::remove_file(path).is_ok() {
+                        result = 0x00;
+                    }
+                }
+                self.ret8(result);
+            }
+            // Read sequential
+            20 => {
+                let record = self.fcb_record(de);
+                let result = self.read_record(de, record);
+                if result == 0x00 {
+                    self.set_fcb_record(de, record + 1);
+                }
+                self.ret8(result);
+            }
+            // Write sequential
+            21 => {
+                let record = self.fcb_record(de);
+                let result = self.write_record(de, record);
+                if result == 0x00 {
+                    self.set_fcb_record(de, record + 1);
+                }
+                self.ret8(result);
+            }
+            // Make file
+            22 => {
+                let name = self.read_fcb_name(de);
+                let path = match self.host_path(de) {
+                    Some(path) => path,
+                    None => self.root.join(host_filename(&name)),
+                };
+                let result = match File::create(path) {
+                    Ok(_) => {
+                        for offset in 12..16 {
+                            self.poke(de + offset, 0x00);
+                        }
+                        self.poke(de + 32, 0x00);
+                        0x00
+                    }
+                    Err(_) => 0xFF,
+                };
+                self.ret8(result);
+            }
+            // Rename file
+            23 => {
+                let mut new_name = [b' '; 11];
+                for (i, byte) in new_name.iter_mut().enumerate() {
+                    *byte = self.peek(de + 17 + i as u16) & 0x7F;
+                }
+                let exists = self.directory().iter().any(|&(_, ref name)| *name == new_name);
+                let result = match self.host_path(de) {
+                    Some(ref path) if !exists => {
+                        match fs::rename(path, self.root.join(host_filename(&new_name))) {
+                            Ok(_) => 0x00,
+                            Err(_) => 0xFF,
+                        }
+                    }
+                    _ => 0xFF,
+                };
+                self.ret8(result);
+            }
+            // Return login vector
+            24 => self.ret16(0x0001),
+            // Return current disk
+            25 => self.ret8(0x00),
+            // Set DMA address
+            26 => self.dma = de,
+            // Get/set user code
+            32 => self.ret8(0x00),
+            // Read random
+            33 => {
+                let record = self.random_record(de);
+                let result = self.read_record(de, record);
+                if result == 0x00 {
+                    self.set_fcb_record(de, record);
+                }
+                self.ret8(result);
+            }
+            // Write random, write random with zero fill
+            34 | 40 => {
+                let record = self.random_record(de);
+                let result = self.write_record(de, record);
+                if result == 0x00 {
+                    self.set_fcb_record(de, record);
+                }
+                self.ret8(result);
+            }
+            // Compute file size
+            35 => {
+                let result = match self.host_path(de) {
+                    Some(path) => {
+                        let records = file_records(&path);
+                        self.set_random_record(de, records);
+                        0x00
+                    }
+                    None => 0xFF,
+                };
+                self.ret8(result);
+            }
+            // Set random record
+            36 => {
+                let record = self.fcb_record(de);
+                self.set_random_record(de, record);
+            }
+            // Allocation vectors, disk parameters, write protection and
+            // the other functions that have no meaning on a host directory
+            _ => self.ret8(0xFF),
+        }
+        true
+    }
+
+    fn ret8(&mut self, val: u8) {
+        self.ret16(val as u16);
+    }
+
+    // The BDOS returns single byte values in A and L and words in HL and BA
+    fn ret16(&mut self, val: u16) {
+        self.cpu.write_reg16(Reg16::HL, val);
+        self.cpu.write_reg8(Reg8::A, val as u8);
+        self.cpu.write_reg8(Reg8::B, (val >> 8) as u8);
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.memory.borrow().read_word(addr)
+    }
+
+    fn poke(&mut self, addr: u16, val: u8) {
+        self.memory.borrow_mut().write_word(addr, val);
+    }
+
+    fn console_read(&mut self) -> u8 {
+        let mut c = [0u8; 1];
+        match self.console_in.read(&mut c) {
+            Ok(1) if c[0] == b'\n' => b'\r',
+            Ok(1) => c[0],
+            _ => EOF_MARKER,
+        }
+    }
+
+    fn console_write(&mut self, c: u8) {
+        self.console_out.write_all(&[c]).unwrap();
+        self.console_out.flush().unwrap();
+    }
+
+    fn read_console_buffer(&mut self, buffer: u16) {
+        let max_len = self.peek(buffer);
+        let mut len = 0;
+        loop {
+            let mut c = [0u8; 1];
+            match self.console_in.read(&mut c) {
+                Ok(1) if c[0] == b'\n' => break,
+                Ok(1) if c[0] == b'\r' => continue,
+                Ok(1) => {
+                    if len < max_len {
+                        self.poke(buffer + 2 + len as u16, c[0]);
+                        len += 1;
+                    }
+                }
+                _ => break,
+            }
+        }
+        self.poke(buffer + 1, len);
+    }
+
+    fn search_next(&mut self) {
+        match self.search_results.pop() {
+            Some(name) => {
+                let dma = self.dma;
+                let records = self.directory().iter()
+                    .find(|&&(_, ref entry)| *entry == name)
+                    .map_or(0, |&(ref path, _)| file_records(path));
+                let extent = if records > 0 { (records - 1) / RECORDS_PER_EXTENT } else { 0 };
+
+                for offset in 0..32 {
+                    self.poke(dma + offset, 0x00);
+                }
+                self.write_fcb_name(dma, &name);
+                self.poke(dma + 12, (extent % 32) as u8);
+                self.poke(dma + 14, (extent / 32) as u8);
+                self.poke(dma + 15, (records - extent * RECORDS_PER_EXTENT) as u8);
+                self.ret8(0x00);
+            }
+            None => self.ret8(0xFF),
+        }
+    }
+
+    fn read_fcb_name(&self, fcb: u16) -> [u8; 11] {
+        let mut name = [b' '; 11];
+        for (i, byte) in name.iter_mut().enumerate() {
+            *byte = self.peek(fcb + 1 + i as u16) & 0x7F;
+        }
+        name
+    }
+
+    fn write_fcb_name(&mut self, fcb: u16, name: &[u8; 11]) {
+        for (i, byte) in name.iter().enumerate() {
+            self.poke(fcb + 1 + i as u16, *byte);
+        }
+    }
+
+    // Every regular file in the root directory that has a valid 8.3 name
+    fn directory(&self) -> Vec<(PathBuf, [u8; 11])> {
+        let mut entries = Vec::new();
+        if let Ok(dir) = fs::read_dir(&self.root) {
+            for entry in dir.filter_map(|entry| entry.ok()) {
+                let path = entry.path();
+                if !path.is_file() { continue; }
+                let name = match entry.file_name().to_str().and_then(cpm_filename) {
+                    Some(name) => name,
+                    None => continue,
+                };
+                entries.push((path, name));
+            }
+        }
+        entries.sort_by(|a, b| a.1.cmp(&b.1));
+        entries
+    }
+
+    fn host_path(&self, fcb: u16) -> Option<PathBuf> {
+        let name = self.read_fcb_name(fcb);
+        self.directory().into_iter()
+            .find(|&(_, ref entry)| *entry == name)
+            .map(|(path, _)| path)
+    }
+
+    fn fcb_record(&self, fcb: u16) -> u64 {
+        let ex = (self.peek(fcb + 12) & 0x1F) as u64;
+        let s2 = (self.peek(fcb + 14) & 0x3F) as u64;
+        let cr = (self.peek(fcb + 32) & 0x7F) as u64;
+        (s2 * 32 + ex) * RECORDS_PER_EXTENT + cr
+    }
+
+    fn set_fcb_record(&mut self, fcb: u16, record: u64) {
+        let extent = record / RECORDS_PER_EXTENT;
+        self.poke(fcb + 32, (record % RECORDS_PER_EXTENT) as u8);
+        self.poke(fcb + 12, (extent % 32) as u8);
+        self.poke(fcb + 14, (extent / 32) as u8);
+        if let Some(path) = self.host_path(fcb) {
+            self.update_record_count(fcb, &path, record);
+        }
+    }
+
+    // RC holds the number of records in the extent that contains `record`
+    fn update_record_count(&mut self, fcb: u16, path: &Path, record: u64) {
+        let extent_start = (record / RECORDS_PER_EXTENT) * RECORDS_PER_EXTENT;
+        let records = file_records(path).saturating_sub(extent_start);
+        self.poke(fcb + 15, records.min(RECORDS_PER_EXTENT) as u8);
+    }
+
+    fn random_record(&self, fcb: u16) -> u64 {
+        (self.peek(fcb + 33) as u64) |
+        ((self.peek(fcb + 34) as u64) << 8) |
+        (((self.peek(fcb + 35) & 0x03) as u64) << 16)
+    }
+
+    fn set_random_record(&mut self, fcb: u16, record: u64) {
+        self.poke(fcb + 33, record as u8);
+        self.poke(fcb + 34, (record >> 8) as u8);
+        self.poke(fcb + 35, (record >> 16) as u8);
+    }
+
+    // Returns 0 on success and 1 when reading past the end of the file
+    fn read_record(&mut self, fcb: u16, record: u64) -> u8 {
+        let path = match self.host_path(fcb) {
+            Some(path) => path,
+            None => return 0xFF,
+        };
+
+        let mut buffer = [EOF_MARKER; RECORD_SIZE];
+        let mut len = 0;
+        if let Ok(mut file) = File::open(path) {
+            if file.seek(SeekFrom::Start(record * RECORD_SIZE as u64)).is_ok() {
+                while len < RECORD_SIZE {
+                    match file.read(&mut buffer[len..]) {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => len += n,
+                    }
+                }
+            }
+        }
+        if len == 0 {
+            return 0x01;
+        }
+
+        let dma = self.dma;
+        for (i, byte) in buffer.iter().enumerate() {
+            self.poke(dma + i as u16, *byte);
+        }
+        0x00
+    }
+
+    // Returns 0 on success and 2 when the host refuses the write
+    fn write_record(&mut self, fcb: u16, record: u64) -> u8 {
+        let path = match self.host_path(fcb) {
+            Some(path) => path,
+            None => return 0xFF,
+        };
+
+        let mut buffer = [0u8; RECORD_SIZE];
+        for (i, byte) in buffer.iter_mut().enumerate() {
+            *byte = self.peek(self.dma + i as u16);
+        }
+
+        let written = OpenOptions::new().write(true).open(path)
+            .and_then(|mut file| {
+                file.seek(SeekFrom::Start(record * RECORD_SIZE as u64))?;
+                file.write_all(&buffer)
+            });
+        match written {
+            Ok(_) => 0x00,
+            Err(_) => 0x02,
+        }
+    }
+}
+
+fn file_records(path: &Path) -> u64 {
+    let size = fs::metadata(path).map(|metadata| metadata.len()).unwrap_or(0);
+    (size + RECORD_SIZE as u64 - 1) / RECORD_SIZE as u64
+}
+
+fn name_matches(pattern: &[u8; 11], name: &[u8; 11]) -> bool {
+    pattern.iter().zip(name.iter()).all(|(p, n)| *p == b'?' || p == n)
+}
+
+// Parse a CCP style "D:NAME.TYP" argument, expanding '*' into '?'
+fn parse_filename(arg: &str) -> (u8, [u8; 11]) {
+    let arg = arg.to_uppercase();
+    let (drive, filename) = match arg.find(':') {
+        Some(1) => {
+            let drive = arg.as_bytes()[0];
+            let drive = if drive >= b'A' && drive <= b'P' { drive - b'A' + 1 } else { 0 };
+            (drive, &arg[2..])
+        }
+        _ => (0, &arg[..]),
+    };
+
+    let (base, ext) = match filename.find('.') {
+        Some(i) => (&filename[..i], &filename[i + 1..]),
+        None => (filename, ""),
+    };
+
+    let mut name = [b' '; 11];
+    fill_name_field(&mut name[0..8], base);
+    fill_name_field(&mut name[8..11], ext);
+    (drive, name)
+}
+
+fn fill_name_field(field: &mut [u8], part: &str) {
+    for (i, c) in part.bytes().enumerate() {
+        if i >= field.len() { break; }
+        if c == b'*' {
+            for byte in field[i..].iter_mut() {
+                *byte = b'?';
+            }
+            break;
+        }
+        field[i] = c;
+    }
+}
+
+// Map a host file name to its FCB form, if it is a valid 8.3 name
+fn cpm_filename(filename: &str) -> Option<[u8; 11]> {
+    let (base, ext) = match filename.find('.') {
+        Some(i) => (&filename[..i], &filename[i + 1..]),
+        None => (filename, ""),
+    };
+    let valid = |part: &str, max_len: usize| {
+        part.len() <= max_len &&
+        part.bytes().all(|c| c.is_ascii_graphic() && !b".:*?".contains(&c))
+    };
+    if base.is_empty() || !valid(base, 8) || !valid(ext, 3) {
+        return None;
+    }
+    Some(parse_filename(filename).1)
+}
+
+fn host_filename(name: &[u8; 11]) -> String {
+    let base = String::from_utf8_lossy(&name[0..8]).trim_end().to_lowercase();
+    let ext = String::from_utf8_lossy(&name[8..11]).trim_end().to_lowercase();
+    if ext.is_empty() { base } else { format!("{}.{}", base, ext) }
+}
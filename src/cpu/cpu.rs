@@ -93,7 +93,6 @@ bitflags! {
     }
 }
 
-#[derive(RustcEncodable, RustcDecodable)]
 pub struct Cpu {
     // main register set
     a: u8,
@@ -146,6 +145,9 @@ pub struct Cpu {
     // T Cycle counter
     pub tcycles: u32,
 
+    // T Cycles between two frame interrupts
+    frame_tcycles: u32,
+
     // HALT state
     halted: bool,
 
@@ -185,6 +187,7 @@ impl Cpu {
             halted: false,
 
             tcycles: 0,
+            frame_tcycles: 70908,
 
             interconnect: interconnect,
         }
@@ -408,11 +411,35 @@ impl Cpu {
         }
     }
 
+    pub fn set_frame_tcycles(&mut self, val: u32) {
+        self.frame_tcycles = val;
+    }
+    pub fn get_frame_tcycles(&self) -> u32 {
+        self.frame_tcycles
+    }
+
+    // Finish the current frame now, regardless of how many T Cycles it took
+    pub fn end_frame(&mut self) {
+        self.tcycles = self.frame_tcycles;
+    }
+
+    pub fn interconnect(&self) -> &Interconnect {
+        &self.interconnect
+    }
+    pub fn interconnect_mut(&mut self) -> &mut Interconnect {
+        &mut self.interconnect
+    }
+
     pub fn handle_interrupts(&mut self) {
-        if self.tcycles >= 70908 {
-            self.tcycles -= 70908;
+        let frame_end = self.tcycles >= self.frame_tcycles;
+        if frame_end {
+            self.tcycles -= self.frame_tcycles;
+        }
 
+        if self.interconnect.interrupt_requested(frame_end) {
             if self.iff1 {
+                let start_tcycles = self.tcycles;
+
                 if self.is_halted() {
                     self.inc_pc(1);
                     self.resume();
@@ -423,6 +450,8 @@ impl Cpu {
                 self.inc_r(1);
                 self.tcycles += 7;
 
+                let data_bus = self.interconnect.interrupt_acknowledge();
+
                 let curr_pc = self.pc;
                 let curr_sp = self.sp;
                 self.write_word(curr_sp - 1, ((curr_pc & 0xFF00) >> 8) as u8);
@@ -431,26 +460,46 @@ impl Cpu {
 
                 match self.im {
                     0 => {
-                        self.pc = 0x0038;
+                        // Only RST instructions are supported on the data bus
+                        self.pc = if data_bus & 0xC7 == 0xC7 {
+                            (data_bus & 0x38) as u16
+                        } else {
+                            0x0038
+                        };
                     }
                     1 => {
                         self.pc = 0x0038;
                     }
                     2 => {
-                        let addr = 256u16 * (self.i as u16) + 256u16;
+                        let addr = ((self.i as u16) << 8) | data_bus as u16;
                         let low  = self.read_word(addr);
-                        let high = self.read_word(addr + 1);
+                        let high = self.read_word(addr.wrapping_add(1));
                         self.pc = ((high as u16) << 8 ) | low as u16;
                     }
                     _ => {
                         unreachable!();
                     }
                 }
+
+                self.interconnect.clock(self.tcycles - start_tcycles);
             }
         }
     }
 
+    // Let the peripherals see RETI on the data bus
+    pub fn reti(&mut self) {
+        self.interconnect.reti();
+    }
+
     pub fn run_instruction(&mut self) {
+        let start_tcycles = self.tcycles;
+
+        self.execute_instruction();
+
+        self.interconnect.clock(self.tcycles - start_tcycles);
+    }
+
+    fn execute_instruction(&mut self) {
         let i0 = self.fetch_op();
 
         match i0 {
@@ -309,6 +309,34 @@ impl Instruction for RetN {
 }
 
 
+struct RetI;
+
+impl Instruction for RetI {
+    fn execute(&self, cpu: &mut Cpu) {
+        if cpu.get_iff2() { cpu.set_iff1(); } else { cpu.clear_iff1(); }
+
+        let curr_sp = cpu.read_reg16(Reg16::SP);
+
+        let low  = cpu.read_word(curr_sp);
+        let high = cpu.read_word(curr_sp + 1);
+
+        cpu.write_reg16(Reg16::SP, curr_sp + 2);
+
+        cpu.set_pc(((high as u16) << 8 ) | low as u16);
+
+        cpu.reti();
+    }
+
+    fn get_accessed_regs(&self) -> (OutputRegisters, OutputRegisters) {
+        (OSP, OSP)
+    }
+
+    fn get_string(&self, cpu: &Cpu, _memory: &Memory) -> String {
+        format!("{:#06x}: RETI", cpu.get_pc() - 1)
+    }
+}
+
+
 struct Im { mode: u8 }
 
 impl Instruction for Im {
@@ -1009,7 +1037,7 @@ pub const INSTR_TABLE_ED: [&'static Instruction; 256] = [
     &InRPortC{r:Reg8::B}, &OutPortCR{r:Reg8::B}, &SbcHlSs{r:Reg16::BC}, &LdMemNnDd{r:Reg16::BC}, &Neg        , &RetN       , &Im{mode:0} , &LdIA       ,
 
     /* 0x48 */            /* 0x49 */             /* 0x4A */             /* 0x4B */               /* 0x4C */    /* 0x4D */    /* 0x4E */    /* 0x4F */
-    &InRPortC{r:Reg8::C}, &OutPortCR{r:Reg8::C}, &AdcHlSs{r:Reg16::BC}, &LdDdMemNn{r:Reg16::BC}, &Neg        , &RetN       , &Im{mode:0} , &LdRA       ,
+    &InRPortC{r:Reg8::C}, &OutPortCR{r:Reg8::C}, &AdcHlSs{r:Reg16::BC}, &LdDdMemNn{r:Reg16::BC}, &Neg        , &RetI       , &Im{mode:0} , &LdRA       ,
 
     /* 0x50 */            /* 0x51 */             /* 0x52 */             /* 0x53 */               /* 0x54 */    /* 0x55 */    /* 0x56 */    /* 0x57 */
     &InRPortC{r:Reg8::D}, &OutPortCR{r:Reg8::D}, &SbcHlSs{r:Reg16::DE}, &LdMemNnDd{r:Reg16::DE}, &Neg        , &RetN       , &Im{mode:1} , &LdAI       ,
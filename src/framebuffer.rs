@@ -0,0 +1,134 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+// An RGB24 image, row by row with no padding, that front-ends can blit or
+// save as they see fit
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    data: Vec<u8>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Framebuffer {
+            width,
+            height,
+            data: vec![0; width * height * 3],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // Bytes per row
+    pub fn pitch(&self) -> usize {
+        self.width * 3
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn get_pixel(&self, x: usize, y: usize) -> (u8, u8, u8) {
+        let offset = y * self.pitch() + x * 3;
+        (self.data[offset], self.data[offset + 1], self.data[offset + 2])
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, (r, g, b): (u8, u8, u8)) {
+        let offset = y * self.pitch() + x * 3;
+        self.data[offset + 0] = r;
+        self.data[offset + 1] = g;
+        self.data[offset + 2] = b;
+    }
+
+    // Binary PPM (P6)
+    pub fn write_ppm<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write!(out, "P6\n{} {}\n255\n", self.width, self.height)?;
+        out.write_all(&self.data)
+    }
+
+    // Truecolour PNG. The image data is stored uncompressed, which keeps the
+    // encoder small and is plenty for screenshots.
+    pub fn write_png<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        out.write_all(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A])?;
+
+        let mut ihdr = Vec::new();
+        ihdr.extend_from_slice(&be_u32(self.width as u32));
+        ihdr.extend_from_slice(&be_u32(self.height as u32));
+        // 8 bits per channel, RGB, deflate, no filtering, no interlace
+        ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+        write_png_chunk(out, b"IHDR", &ihdr)?;
+
+        // Every row starts with filter type 0
+        let mut raw = Vec::with_capacity((self.pitch() + 1) * self.height);
+        for row in self.data.chunks(self.pitch()) {
+            raw.push(0);
+            raw.extend_from_slice(row);
+        }
+
+        // zlib stream made of stored deflate blocks
+        let mut idat = vec![0x78, 0x01];
+        let mut blocks = raw.chunks(0xFFFF).peekable();
+        while let Some(block) = blocks.next() {
+            let last = if blocks.peek().is_none() { 1 } else { 0 };
+            let len = block.len() as u16;
+            idat.push(last);
+            idat.extend_from_slice(&[len as u8, (len >> 8) as u8,
+                                     !len as u8, (!len >> 8) as u8]);
+            idat.extend_from_slice(block);
+        }
+        idat.extend_from_slice(&be_u32(adler32(&raw)));
+        write_png_chunk(out, b"IDAT", &idat)?;
+
+        write_png_chunk(out, b"IEND", &[])
+    }
+
+    // The format follows the extension: .png, anything else is PPM
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let is_png = path.as_ref().extension()
+            .and_then(|ext| ext.to_str())
+            .map_or(false, |ext| ext.eq_ignore_ascii_case("png"));
+        let mut out = BufWriter::new(File::create(path)?);
+        if is_png {
+            self.write_png(&mut out)
+        } else {
+            self.write_ppm(&mut out)
+        }
+    }
+}
+
+fn be_u32(val: u32) -> [u8; 4] {
+    [(val >> 24) as u8, (val >> 16) as u8, (val >> 8) as u8, val as u8]
+}
+
+fn write_png_chunk<W: Write>(out: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&be_u32(data.len() as u32))?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+
+    let mut crc = 0xFFFFFFFFu32;
+    for &byte in kind.iter().chain(data.iter()) {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB88320 } else { crc >> 1 };
+        }
+    }
+    out.write_all(&be_u32(!crc))
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
@@ -1,7 +1,7 @@
 use super::peripherals::*;
 
 use std::rc::Rc;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 
 macro_rules! println_if_trace {
     ($fmt: expr, $( $t: expr ),*) => {{
@@ -10,16 +10,31 @@ macro_rules! println_if_trace {
     }}
 }
 
-#[derive(RustcEncodable, RustcDecodable)]
+// A peripheral that answers every port for which port & mask == value
+struct IoDevice {
+    mask: u16,
+    value: u16,
+    device: Rc<RefCell<dyn Peripheral>>,
+}
+
 pub struct Interconnect {
     memory: Rc<RefCell<Memory>>,
 
-    ay: Rc<RefCell<Ay>>,
+    // A second AY makes a Turbosound, and which one the AY ports talk to is
+    // picked through the register select port
+    ays: Vec<Rc<RefCell<Ay>>>,
+    active_ay: Cell<usize>,
+
+    ula: Option<Rc<RefCell<Ula>>>,
 
-    ula: Rc<RefCell<Ula>>,
+    ula_timing: UlaTiming,
 
-    ula_contention: Vec<u8>,
-    ula_contention_no_mreq: Vec<u8>,
+    devices: Vec<IoDevice>,
+
+    contention: bool,
+    // Clock of the CPU when it differs from the ULA's, the beam follows the
+    // ULA clock
+    cpu_hz: Option<u32>,
 }
 
 impl Interconnect {
@@ -27,28 +42,182 @@ impl Interconnect {
                ay : Rc<RefCell<Ay>>,
                ula : Rc<RefCell<Ula>>) -> Self {
 
-        let ula_contention = include_bytes!("peripherals/ulacontention.bin");
-        let ula_contention_no_mreq = include_bytes!("peripherals/ulacontentionnomreq.bin");
+        Interconnect {
+            memory,
+            ays: vec![ay],
+            active_ay: Cell::new(0),
+            ula: Some(ula),
+            ula_timing: SPECTRUM_128K,
+            devices: Vec::new(),
+            contention: true,
+            cpu_hz: None,
+        }
+    }
 
+    // A plain memory bus for non-Spectrum machines: no ULA, no AY, no
+    // 0x7ffd paging and no contention
+    pub fn without_peripherals(memory: Rc<RefCell<Memory>>) -> Self {
         Interconnect {
             memory,
-            ay,
-            ula,
-            ula_contention: ula_contention.to_vec(),
-            ula_contention_no_mreq: ula_contention_no_mreq.to_vec(),
+            ays: Vec::new(),
+            active_ay: Cell::new(0),
+            ula: None,
+            ula_timing: SPECTRUM_128K,
+            devices: Vec::new(),
+            contention: false,
+            cpu_hz: None,
+        }
+    }
+
+    // Attached devices take precedence over the built-in Spectrum ports.
+    // The order of attachment is also the interrupt daisy chain order, from
+    // the highest priority device (IEI tied high) down.
+    pub fn attach(&mut self, device: Rc<RefCell<dyn Peripheral>>, mask: u16, value: u16) {
+        self.devices.push(IoDevice { mask, value, device });
+    }
+
+    fn device_at(&self, port: u16) -> Option<&Rc<RefCell<dyn Peripheral>>> {
+        self.devices.iter()
+            .find(|io| port & io.mask == io.value)
+            .map(|io| &io.device)
+    }
+
+    pub fn clock(&self, tcycles: u32) {
+        for io in &self.devices {
+            io.device.borrow_mut().clock(tcycles);
+        }
+    }
+
+    // First device down the chain that requests an interrupt, unless one
+    // above it is being serviced
+    fn interrupting_device(&self) -> Option<&Rc<RefCell<dyn Peripheral>>> {
+        for io in &self.devices {
+            let mut device = io.device.borrow_mut();
+            if device.interrupt_pending() {
+                return Some(&io.device);
+            }
+            if device.interrupt_in_service() {
+                return None;
+            }
+        }
+        None
+    }
+
+    // The ULA raises /INT once per frame, attached devices hold it low for
+    // as long as they need servicing
+    pub fn interrupt_requested(&self, frame_end: bool) -> bool {
+        (frame_end && self.ula.is_some()) || self.interrupting_device().is_some()
+    }
+
+    // Returns the byte on the data bus during the acknowledge cycle; the
+    // Spectrum leaves it floating at 0xFF
+    pub fn interrupt_acknowledge(&self) -> u8 {
+        match self.interrupting_device() {
+            Some(device) => device.borrow_mut().interrupt_acknowledge(),
+            None => 0xFF,
+        }
+    }
+
+    // Every device decodes RETI, but only the highest priority one under
+    // service reacts to it
+    pub fn reti(&self) {
+        for io in &self.devices {
+            let mut device = io.device.borrow_mut();
+            if device.interrupt_in_service() {
+                device.reti();
+                return;
+            }
+        }
+    }
+
+    // Contention only makes sense for a CPU no faster than the ULA, so turbo
+    // modes turn it off
+    pub fn set_contention(&mut self, val: bool) {
+        self.contention = val && self.ula.is_some();
+    }
+
+    // None leaves T states as they are, for a CPU that runs flat out
+    pub fn set_cpu_hz(&mut self, hz: Option<u32>) {
+        self.cpu_hz = hz;
+    }
+
+    // Where the ULA is in its frame at a CPU T state
+    pub fn ula_tcycle(&self, curr_tcycle: u32) -> u32 {
+        match self.cpu_hz {
+            Some(hz) => (curr_tcycle as u64 * self.ula_timing.clock_hz as u64 / hz as u64) as u32,
+            None => curr_tcycle,
+        }
+    }
+
+    pub fn set_ula_timing(&mut self, timing: UlaTiming) {
+        self.ula_timing = timing;
+        if let Some(ref ula) = self.ula {
+            ula.borrow_mut().set_timing(timing);
+        }
+    }
+
+    pub fn get_ula_timing(&self) -> UlaTiming {
+        self.ula_timing
+    }
+
+    // For models without a sound chip, whose AY ports are left unattached
+    pub fn remove_ay(&mut self) {
+        self.ays.clear();
+        self.active_ay.set(0);
+    }
+
+    pub fn add_ay(&mut self, ay: Rc<RefCell<Ay>>) {
+        self.ays.push(ay);
+    }
+
+    fn active_ay(&self) -> Option<&Rc<RefCell<Ay>>> {
+        self.ays.get(self.active_ay.get())
+    }
+
+    // RAM bank paged in at an address, None for ROM
+    fn bank_at(&self, addr: u16) -> Option<u8> {
+        let memory = self.memory.borrow();
+        match addr {
+            0x4000...0x7FFF => Some(memory.get_4000_bank()),
+            0x8000...0xBFFF => Some(memory.get_8000_bank()),
+            0xC000...0xFFFF => Some(memory.get_c000_bank()),
+            _ => None,
         }
     }
 
     fn is_addr_contended(&self, addr: u16) -> bool {
-        (addr >= 0x4000 && addr < 0x8000) ||
-        (addr >= 0xC000 && (self.memory.borrow().get_c000_bank() % 2 != 0))
+        if !self.contention {
+            return false;
+        }
+
+        match self.bank_at(addr) {
+            Some(bank) => self.ula_timing.is_bank_contended(bank),
+            None => false,
+        }
+    }
+
+    // Whether a write changes the bitmap or attributes the ULA shows, which
+    // on the 128K can also be reached through 0xC000
+    fn is_screen_addr(&self, addr: u16) -> bool {
+        let screen = self.memory.borrow().get_4000_bank();
+        self.bank_at(addr) == Some(screen) && (addr & 0x3FFF) < 0x1B00
+    }
+
+    // Contention delay at a CPU T state. The ULA counts it in its own T
+    // states, which at clocks slower than its own round to as many CPU ones.
+    fn delay(&self, curr_tcycle: u32) -> u32 {
+        self.ula_timing.delay(self.ula_tcycle(curr_tcycle))
+    }
+
+    fn is_port_contended(&self, port: u16) -> bool {
+        self.ula_timing.contended_ports && self.is_addr_contended(port)
     }
 
     #[inline(always)]
     pub fn contend_read(&self, addr: u16, curr_tcycle: u32, tcycles: u32) -> u32 {
         println_if_trace!("{: >5} MC {:04x}", curr_tcycle, addr);
         let delay = if self.is_addr_contended(addr) {
-            self.ula_contention[curr_tcycle as usize] as u32
+            self.delay(curr_tcycle)
         } else {
             0
         };
@@ -59,7 +228,7 @@ impl Interconnect {
     pub fn contend_read_no_mreq(&self, addr: u16, curr_tcycle: u32) -> u32 {
         println_if_trace!("{: >5} MC {:04x}", curr_tcycle, addr);
         let delay = if self.is_addr_contended(addr) {
-            self.ula_contention_no_mreq[curr_tcycle as usize] as u32
+            self.delay(curr_tcycle)
         } else {
             0
         };
@@ -70,7 +239,7 @@ impl Interconnect {
     pub fn contend_write_no_mreq(&self, addr: u16, curr_tcycle: u32) -> u32 {
         println_if_trace!("{: >5} MC {:04x}", curr_tcycle, addr);
         let delay = if self.is_addr_contended(addr) {
-            self.ula_contention_no_mreq[curr_tcycle as usize] as u32
+            self.delay(curr_tcycle)
         } else {
             0
         };
@@ -84,16 +253,21 @@ impl Interconnect {
         val
     }
 
-    #[cfg_attr(not(feature = "trace-interconnect"), allow(unused_variables))]
     pub fn write_word(&self, addr: u16, val: u8, curr_tcycle: u32) {
+        // The beam has to draw the old contents first
+        if let Some(ref ula) = self.ula {
+            if self.is_screen_addr(addr) {
+                ula.borrow_mut().catch_up(self.ula_tcycle(curr_tcycle));
+            }
+        }
         self.memory.borrow_mut().write_word(addr, val);
         println_if_trace!("{: >5} MW {:04x} {:02x}", curr_tcycle, addr, val);
     }
 
     pub fn contend_port_early(&self, port: u16, curr_tcycle: u32) -> u32 {
-        let delay = if self.is_addr_contended(port) {
+        let delay = if self.is_port_contended(port) {
             println_if_trace!("{: >5} PC {:04x}", curr_tcycle, port);
-            self.ula_contention_no_mreq[curr_tcycle as usize] as u32
+            self.delay(curr_tcycle)
         } else {
             0
         };
@@ -101,18 +275,18 @@ impl Interconnect {
     }
 
     pub fn contend_port_late(&self, port: u16, curr_tcycle: u32) -> u32 {
-        let delay = if (port & 0x0001) == 0 {
+        let delay = if (port & 0x0001) == 0 && self.contention && self.ula_timing.contended_ports {
             println_if_trace!("{: >5} PC {:04x}", curr_tcycle, port);
-            (self.ula_contention_no_mreq[curr_tcycle as usize] as u32) + 2
+            self.delay(curr_tcycle) + 2
         } else {
-            if self.is_addr_contended(port) {
+            if self.is_port_contended(port) {
                 let mut delay: u32 = 0;
                 println_if_trace!("{: >5} PC {:04x}", curr_tcycle + delay, port);
-                delay += (self.ula_contention_no_mreq[(curr_tcycle + delay) as usize] as u32) + 1;
+                delay += self.delay(curr_tcycle + delay) + 1;
                 println_if_trace!("{: >5} PC {:04x}", curr_tcycle + delay, port);
-                delay += (self.ula_contention_no_mreq[(curr_tcycle + delay) as usize] as u32) + 1;
+                delay += self.delay(curr_tcycle + delay) + 1;
                 println_if_trace!("{: >5} PC {:04x}", curr_tcycle + delay, port);
-                delay += self.ula_contention_no_mreq[(curr_tcycle + delay) as usize] as u32;
+                delay += self.delay(curr_tcycle + delay);
                 delay
             } else {
                 2
@@ -121,25 +295,53 @@ impl Interconnect {
         delay + 1
     }
 
-    #[cfg_attr(not(feature = "trace-interconnect"), allow(unused_variables))]
+    // Nothing drives the data bus, so it holds the byte the ULA last put on
+    // it: screen data during the display fetches, 0xFF otherwise. The paging
+    // port is write-only, so reading it gets the same.
+    pub fn floating_bus(&self, curr_tcycle: u32) -> u8 {
+        match self.ula_timing.fetch_address(self.ula_tcycle(curr_tcycle)) {
+            Some(addr) => self.memory.borrow().read_word(addr),
+            None => 0xFF,
+        }
+    }
+
     pub fn read_port(&self, port: u16, curr_tcycle: u32) -> u8 {
-        let val = match port {
-            port if port & 0x0001 == 0 => self.ula.borrow().read_port(port),
-            0x7ffd => self.memory.borrow().read_port(port),
-            0xfffd | 0xbffd => self.ay.borrow().read_port(port),
+        if let Some(device) = self.device_at(port) {
+            let val = device.borrow_mut().read_port(port);
+            println_if_trace!("{: >5} PR {:04x} {:02x}", curr_tcycle, port, val);
+            return val;
+        }
+
+        let val = match (port, &self.ula, self.active_ay()) {
+            (port, &Some(ref ula), _) if port & 0x0001 == 0 => ula.borrow_mut().read_port_at(port, curr_tcycle),
+            (ULAPLUS_DATA_PORT, &Some(ref ula), _) if ula.borrow().has_ulaplus() => ula.borrow().read_ulaplus_port(port),
+            (AY_SELECT_PORT, _, Some(ay)) => ay.borrow_mut().read_port(port),
+            (_, &Some(_), _) => self.floating_bus(curr_tcycle),
             _ => 0,
         };
         println_if_trace!("{: >5} PR {:04x} {:02x}", curr_tcycle, port, val);
         val
     }
 
-    #[cfg_attr(not(feature = "trace-interconnect"), allow(unused_variables))]
     pub fn write_port(&self, port: u16, val: u8, curr_tcycle: u32) {
         println_if_trace!("{: >5} PW {:04x} {:02x}", curr_tcycle, port, val);
-        match port {
-            port if port & 0x0001 == 0 => self.ula.borrow_mut().write_port(port, val),
-            0x7ffd => self.memory.borrow_mut().write_port(port, val),
-            0xfffd | 0xbffd => self.ay.borrow_mut().write_port(port, val),
+        if let Some(device) = self.device_at(port) {
+            device.borrow_mut().write_port(port, val);
+            return;
+        }
+
+        // Turbosound chip select: 0xFF for the first AY, 0xFE for the second
+        if port == AY_SELECT_PORT && self.ays.len() > 1 && val >= 0xFE {
+            self.active_ay.set((0xFF - val) as usize);
+        }
+
+        match (port, &self.ula, self.active_ay()) {
+            (port, &Some(ref ula), _) if port & 0x0001 == 0 => ula.borrow_mut().write_port_at(port, val, curr_tcycle, self.ula_tcycle(curr_tcycle)),
+            (0x7ffd, &Some(_), _) => self.memory.borrow_mut().write_port(port, val),
+            (ULAPLUS_REGISTER_PORT, &Some(ref ula), _) |
+            (ULAPLUS_DATA_PORT, &Some(ref ula), _) => ula.borrow_mut().write_ulaplus_port(port, val, self.ula_tcycle(curr_tcycle)),
+            (AY_SELECT_PORT, _, Some(ay)) |
+            (AY_DATA_PORT, _, Some(ay)) => ay.borrow_mut().write_port_at(port, val, curr_tcycle),
             _ => (),
         };
     }
@@ -7,6 +7,7 @@ extern crate rustc_serialize;
 extern crate bincode;
 #[macro_use]
 extern crate nom;
+#[cfg(feature = "sdl")]
 extern crate sdl2;
 #[macro_use]
 extern crate lazy_static;
@@ -16,6 +17,14 @@ pub mod interconnect;
 pub mod peripherals;
 pub mod debugger;
 pub mod utils;
+pub mod framebuffer;
+pub mod pacing;
+pub mod wav;
+pub mod mixer;
+pub mod aylog;
+pub mod tape;
 pub mod machine;
 pub mod snapshot;
+pub mod cpm;
+pub mod sbc;
 
@@ -1,21 +1,45 @@
 use ::interconnect::*;
 use ::peripherals::*;
 use ::cpu::*;
+#[cfg(feature = "sdl")]
 use ::debugger::*;
 use ::snapshot::*;
 use ::utils::read_bin;
+use ::framebuffer::Framebuffer;
+use ::wav::WavWriter;
+use ::mixer::{Mixer, StereoMode};
+use ::tape::{Tape, TapePlayer};
+#[cfg(feature = "sdl")]
+use ::pacing::{AudioFeeder, FramePacer, SystemClock};
 
 use std::path::Path;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::str::FromStr;
+use std::time::Duration;
+#[cfg(feature = "sdl")]
+use std::time::Instant;
+#[cfg(feature = "sdl")]
+use std::thread;
 
 use std::rc::Rc;
 use std::cell::RefCell;
 
+#[cfg(feature = "sdl")]
 extern crate sdl2;
+#[cfg(feature = "sdl")]
 use sdl2::pixels::PixelFormatEnum;
+#[cfg(feature = "sdl")]
 use sdl2::rect::Rect;
+#[cfg(feature = "sdl")]
 use sdl2::event::Event;
+#[cfg(feature = "sdl")]
 use sdl2::keyboard::Keycode;
+#[cfg(feature = "sdl")]
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
 
+#[cfg(feature = "sdl")]
 use std::collections::HashMap;
 
 #[derive(Hash, Eq, PartialEq, Debug)]
@@ -64,6 +88,7 @@ pub enum SpectrumKeycode {
     NONE,
 }
 
+#[cfg(feature = "sdl")]
 lazy_static! {
     static ref KEYBOARD_MAPPINGS: HashMap<Keycode,(SpectrumKeycode, SpectrumKeycode)> = {
         let mut m = HashMap::new();
@@ -138,90 +163,754 @@ lazy_static! {
     };
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CpuClock {
+    Mhz3_5,
+    Mhz3_5469,
+    Mhz7,
+    Mhz14,
+    Unlimited,
+}
+
+impl CpuClock {
+    pub fn hz(&self) -> Option<u64> {
+        match *self {
+            CpuClock::Mhz3_5    => Some(3_500_000),
+            CpuClock::Mhz3_5469 => Some(3_546_900),
+            CpuClock::Mhz7      => Some(7_093_800),
+            CpuClock::Mhz14     => Some(14_187_600),
+            CpuClock::Unlimited => None,
+        }
+    }
+
+    // CPU T states in one 128K ULA frame, None when frames follow the wall
+    // clock
+    pub fn frame_tcycles(&self) -> Option<u32> {
+        self.frame_tcycles_for(&SPECTRUM_128K)
+    }
+
+    // The ULA runs its frame off its own clock, no matter how fast the CPU is
+    pub fn frame_tcycles_for(&self, timing: &UlaTiming) -> Option<u32> {
+        self.hz().map(|hz| (timing.frame_tcycles as u64 * hz / timing.clock_hz as u64) as u32)
+    }
+
+    // Memory is only contended for a CPU no faster than the ULA, turbo and
+    // unlimited clocks get to run flat out
+    pub fn is_contended(&self, timing: &UlaTiming) -> bool {
+        self.hz().map_or(false, |hz| hz <= timing.clock_hz as u64)
+    }
+}
+
+impl FromStr for CpuClock {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "3.5"       => Ok(CpuClock::Mhz3_5),
+            "3.5469"    => Ok(CpuClock::Mhz3_5469),
+            "7"         => Ok(CpuClock::Mhz7),
+            "14"        => Ok(CpuClock::Mhz14),
+            "unlimited" => Ok(CpuClock::Unlimited),
+            _ => Err(format!("Unknown CPU clock: {}", s)),
+        }
+    }
+}
+
+// Entry point of the ROM routine that loads a block from tape
+pub const LD_BYTES: u16 = 0x0556;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Model {
+    Spectrum16K,
+    Spectrum48K,
+    Spectrum128K,
+}
+
+impl Model {
+    // RAM the model ships with, in KB
+    pub fn ram_size(&self) -> usize {
+        match *self {
+            Model::Spectrum16K  => 16,
+            Model::Spectrum48K  => 48,
+            Model::Spectrum128K => 128,
+        }
+    }
+
+    // Only the 48K model can be fitted with a different amount of RAM
+    pub fn supports_ram_size(&self, kb: usize) -> bool {
+        match *self {
+            Model::Spectrum48K => kb >= 16 && kb <= 48,
+            _ => kb == self.ram_size(),
+        }
+    }
+
+    pub fn ula_timing(&self) -> UlaTiming {
+        match *self {
+            Model::Spectrum128K => SPECTRUM_128K,
+            _ => SPECTRUM_48K,
+        }
+    }
+
+    pub fn native_clock(&self) -> CpuClock {
+        match *self {
+            Model::Spectrum128K => CpuClock::Mhz3_5469,
+            _ => CpuClock::Mhz3_5,
+        }
+    }
+
+    fn memory(&self, ram_size: usize) -> MemoryBuilder {
+        assert!(self.supports_ram_size(ram_size),
+                "{:?} can't have {}K of RAM", self, ram_size);
+        match *self {
+            Model::Spectrum128K => MemoryBuilder::new(),
+            _ => MemoryBuilder::new().ram_size(ram_size),
+        }
+    }
+}
+
+impl FromStr for Model {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "16"  => Ok(Model::Spectrum16K),
+            "48"  => Ok(Model::Spectrum48K),
+            "128" => Ok(Model::Spectrum128K),
+            _ => Err(format!("Unknown model: {}", s)),
+        }
+    }
+}
+
+// Splits the RAM image of a 48K snapshot into banks 5, 2 and 0. An image
+// that only uses the RAM the machine has is cut down to size, or padded if
+// it is short.
+pub fn snapshot_banks(ram_size: usize, mut data: Vec<u8>) -> Result<Vec<Box<[u8]>>, String> {
+    let used = data.iter().rposition(|&x| x != 0).map_or(0, |last| last + 1);
+    if used > ram_size * 1024 {
+        return Err(format!("Snapshot needs more than {}K of RAM", ram_size));
+    }
+    data.resize(3 * 16 * 1024, 0);
+
+    let bank0 = data.split_off(2 * 16 * 1024).into_boxed_slice();
+    let bank2 = data.split_off(16 * 1024).into_boxed_slice();
+    let bank5 = data.into_boxed_slice();
+    Ok(vec![bank5, bank2, bank0])
+}
+
 pub struct Machine {
     cpu: Rc<RefCell<Cpu>>,
     memory: Rc<RefCell<Memory>>,
     ula: Rc<RefCell<Ula>>,
+    // None on models without sound chip, two with Turbosound
+    ays: Vec<Rc<RefCell<Ay>>>,
+    // Specdrum and Covox add-ons
+    dacs: Vec<Rc<RefCell<Dac>>>,
+    mixer: Mixer,
+    framebuffer: Framebuffer,
+    ram_size: usize,
+    model: Model,
+    tape: Option<TapePlayer>,
+    fast_load: bool,
+    timing: UlaTiming,
+    clock: CpuClock,
+    sample_rate: u32,
+    // Mixed sound not yet taken by the front-end, and a copy of it all
+    // when recording
+    audio: Vec<i16>,
+    recorder: Option<WavWriter<BufWriter<File>>>,
+    // Only the SDL front-end has a debugger, paces frames and plays sound
+    #[cfg_attr(not(feature = "sdl"), allow(dead_code))]
     debug_on: bool,
+    #[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+    speed: u32,
+    #[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+    mute: bool,
+    #[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+    audio_sync: bool,
 }
 
 impl Machine {
-    pub fn from_snapshot(start_in_debug: bool, header: &Z80Header, mut data: Vec<u8>) -> Self {
+    pub fn from_snapshot(start_in_debug: bool, model: Model, ram_size: usize,
+                         header: &Z80Header, data: Vec<u8>) -> Result<Self, String> {
         // TODO ROM detection based on snapshot
         let rom0 = read_bin(Path::new("./roms/48.rom"));
-        let bank0 = data.split_off(2 * 16 * 1024).into_boxed_slice();
-        let bank2 = data.split_off(16 * 1024).into_boxed_slice();
-        let bank5 = data.into_boxed_slice();
-        let memory = Rc::new(RefCell::new(MemoryBuilder::new()
+        let mut banks = snapshot_banks(ram_size, data)?;
+        let bank0 = banks.pop().unwrap();
+        let bank2 = banks.pop().unwrap();
+        let bank5 = banks.pop().unwrap();
+        let memory = model.memory(ram_size)
             .rom0(rom0)
             .bank0(bank0)
             .bank2(bank2)
             .bank5(bank5)
-            .finalize()));
+            .finalize();
+
+        let machine = Machine::with_memory(start_in_debug, model, ram_size, memory);
+        machine.load_registers(header);
+        Ok(machine)
+    }
+
+    pub fn new(start_in_debug: bool, model: Model, ram_size: usize) -> Self {
+        let roms = match model {
+            Model::Spectrum128K => vec![read_bin(Path::new("./roms/128-0.rom")),
+                                        read_bin(Path::new("./roms/128-1.rom"))],
+            _ => vec![read_bin(Path::new("./roms/48.rom"))],
+        };
+        Machine::with_roms(start_in_debug, model, ram_size, roms)
+    }
+
+    // A machine booting from the given ROM images instead of the ones in
+    // ./roms, one per 16K ROM bank
+    pub fn with_roms(start_in_debug: bool, model: Model, ram_size: usize,
+                     roms: Vec<Box<[u8]>>) -> Self {
+        let mut roms = roms.into_iter();
+        let mut builder = model.memory(ram_size);
+        if let Some(rom0) = roms.next() {
+            builder = builder.rom0(rom0);
+        }
+        if let Some(rom1) = roms.next() {
+            builder = builder.rom1(rom1);
+        }
+        Machine::with_memory(start_in_debug, model, ram_size, builder.finalize())
+    }
+
+    fn with_memory(start_in_debug: bool, model: Model, ram_size: usize, memory: Memory) -> Self {
+        let memory = Rc::new(RefCell::new(memory));
 
         let ay = Rc::new(RefCell::new(Ay::new()));
         let ula = Rc::new(RefCell::new(Ula::new(memory.clone())));
 
-        let interconnect = Interconnect::new(
+        let mut interconnect = Interconnect::new(
             memory.clone(),
             ay.clone(),
             ula.clone());
+        interconnect.set_ula_timing(model.ula_timing());
+        let ays = if model == Model::Spectrum128K {
+            vec![ay]
+        } else {
+            interconnect.remove_ay();
+            Vec::new()
+        };
 
         let cpu = Rc::new(RefCell::new(Cpu::new(interconnect)));
-        cpu.borrow_mut().write_reg16(Reg16::AF, header.af);
-        cpu.borrow_mut().write_reg16(Reg16::BC, header.bc);
-        cpu.borrow_mut().write_reg16(Reg16::HL, header.hl);
-        cpu.borrow_mut().set_pc(header.pc);
-        cpu.borrow_mut().write_reg16(Reg16::SP, header.sp);
-        cpu.borrow_mut().write_reg8(Reg8::I, header.ir);
-        cpu.borrow_mut().write_reg8(Reg8::R, header.r);
-        cpu.borrow_mut().write_reg16(Reg16::DE, header.de);
-        cpu.borrow_mut().write_reg16(Reg16::BC_ALT, header.bc_alt);
-        cpu.borrow_mut().write_reg16(Reg16::DE_ALT, header.de_alt);
-        cpu.borrow_mut().write_reg16(Reg16::HL_ALT, header.hl_alt);
-        cpu.borrow_mut().write_reg16(Reg16::AF_ALT, header.af_alt);
-        cpu.borrow_mut().write_reg16(Reg16::IX, header.ix);
-        cpu.borrow_mut().write_reg16(Reg16::IY, header.iy);
-        if header.iff1 { cpu.borrow_mut().set_iff1() }
-        if header.iff2 { cpu.borrow_mut().set_iff2() }
-        cpu.borrow_mut().set_im(header.misc2 & 0x03);
-
-        Machine {
+
+        let mut machine = Machine {
             cpu,
             memory,
             ula,
+            ays,
+            dacs: Vec::new(),
+            mixer: Mixer::new(),
+            framebuffer: Framebuffer::new(DISPLAY_WIDTH, DISPLAY_HEIGHT),
+            ram_size,
+            model,
+            tape: None,
+            fast_load: true,
+            timing: model.ula_timing(),
+            clock: model.native_clock(),
+            sample_rate: 44100,
+            audio: Vec::new(),
+            recorder: None,
             debug_on: start_in_debug,
+            speed: 100,
+            mute: false,
+            audio_sync: false,
+        };
+        machine.set_cpu_clock(model.native_clock());
+        machine
+    }
+
+    // Frames keep their real time length, so a faster CPU gets proportionally
+    // more T states per frame. Contention is only emulated at the ULA clock.
+    pub fn set_cpu_clock(&mut self, clock: CpuClock) {
+        let mut cpu = self.cpu.borrow_mut();
+        cpu.set_frame_tcycles(clock.frame_tcycles_for(&self.timing).unwrap_or(u32::max_value()));
+        cpu.interconnect_mut().set_contention(clock.is_contended(&self.timing));
+        cpu.interconnect_mut().set_cpu_hz(clock.hz().map(|hz| hz as u32));
+        if let Some(hz) = clock.hz() {
+            self.ula.borrow_mut().beeper_mut().set_clock(hz as u32);
+            // The AY runs off half the ULA clock
+            for ay in &self.ays {
+                ay.borrow_mut().set_clock(self.timing.clock_hz / 2, hz as u32);
+            }
+            for dac in &self.dacs {
+                dac.borrow_mut().set_clock(hz as u32);
+            }
+            if let Some(ref mut tape) = self.tape {
+                tape.set_clock(hz as u32);
+            }
         }
+        self.clock = clock;
     }
 
-    pub fn new(start_in_debug: bool) -> Self {
-        let rom0 = read_bin(Path::new("./roms/128-0.rom"));
-        let rom1 = read_bin(Path::new("./roms/128-1.rom"));
+    // Real time length of a frame, about 20ms
+    pub fn frame_duration(&self) -> Duration {
+        Duration::new(
+            0, (self.timing.frame_tcycles as u64 * 1_000_000_000 / self.timing.clock_hz as u64) as u32)
+    }
 
-        let memory = Rc::new(RefCell::new(MemoryBuilder::new()
-            .rom0(rom0)
-            .rom1(rom1)
-            .finalize()));
+    // Speed of the window front-end as a percentage of the real machine
+    pub fn set_speed(&mut self, percent: u32) {
+        assert!(percent > 0, "Speed must be above 0%");
+        self.speed = percent;
+    }
 
-        let ay = Rc::new(RefCell::new(Ay::new()));
-        let ula = Rc::new(RefCell::new(Ula::new(memory.clone())));
+    pub fn set_mute(&mut self, val: bool) {
+        self.mute = val;
+    }
 
-        let interconnect = Interconnect::new(
-            memory.clone(),
-            ay.clone(),
-            ula.clone());
+    // Have the window front-end run as fast as the sound is played rather
+    // than follow the wall clock
+    pub fn set_audio_sync(&mut self, val: bool) {
+        self.audio_sync = val;
+    }
 
-        let cpu = Rc::new(RefCell::new(Cpu::new(interconnect)));
+    // Board issue of the 16K and 48K, for the value port 0xFE bit 6 reads
+    // back without a tape. Machines start as issue 3.
+    pub fn set_issue(&mut self, issue: Issue) {
+        self.ula.borrow_mut().set_issue(issue);
+    }
 
-        Machine {
-            cpu,
-            memory,
-            ula,
-            debug_on: start_in_debug,
+    // ULAplus is an add-on, machines start without it
+    pub fn enable_ulaplus(&mut self) {
+        self.ula.borrow_mut().enable_ulaplus();
+    }
+
+    pub fn ulaplus(&self) -> Option<UlaPlus> {
+        self.ula.borrow().ulaplus().cloned()
+    }
+
+    // Replace the palette and mode, turning ULAplus on if it was off
+    pub fn set_ulaplus(&mut self, ulaplus: UlaPlus) {
+        self.ula.borrow_mut().set_ulaplus(ulaplus);
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    // Let the ULA finish the frame and draw it into the framebuffer
+    pub fn update_framebuffer(&mut self) {
+        self.ula.borrow_mut().end_frame();
+        self.ula.borrow().display(&mut self.framebuffer);
+    }
+
+    pub fn cpu(&self) -> Rc<RefCell<Cpu>> {
+        self.cpu.clone()
+    }
+
+    pub fn memory(&self) -> Rc<RefCell<Memory>> {
+        self.memory.clone()
+    }
+
+    pub fn press_key(&mut self, key: SpectrumKeycode) {
+        self.ula.borrow_mut().key_pressed(&key);
+    }
+
+    pub fn release_key(&mut self, key: SpectrumKeycode) {
+        self.ula.borrow_mut().key_released(&key);
+    }
+
+    // 44.1kHz unless set otherwise. A recording in progress is saved at the
+    // new rate.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        if let Some(ref mut recorder) = self.recorder {
+            recorder.set_sample_rate(sample_rate);
+        }
+        self.ula.borrow_mut().beeper_mut().set_sample_rate(sample_rate);
+        for ay in &self.ays {
+            ay.borrow_mut().set_sample_rate(sample_rate);
+        }
+        for dac in &self.dacs {
+            dac.borrow_mut().set_sample_rate(sample_rate);
+        }
+    }
+
+    // Samples of each channel in a frame
+    pub fn samples_per_frame(&self) -> usize {
+        (self.sample_rate as u64 * self.timing.frame_tcycles as u64 / self.timing.clock_hz as u64) as usize
+    }
+
+    // Separation from 0 to 1, how far A and C (or B) are moved to the sides
+    pub fn set_stereo_mode(&mut self, mode: StereoMode, separation: f32) {
+        self.mixer.set_stereo_mode(mode, separation);
+    }
+
+    pub fn mixer_mut(&mut self) -> &mut Mixer {
+        &mut self.mixer
+    }
+
+    // Fit the Turbosound add-on: two AYs, both panned the same way. Models
+    // without a sound chip get both from the add-on.
+    pub fn enable_turbosound(&mut self) {
+        while self.ays.len() < 2 {
+            let mut ay = Ay::new();
+            if let Some(hz) = self.clock.hz() {
+                ay.set_clock(self.timing.clock_hz / 2, hz as u32);
+            }
+            ay.set_sample_rate(self.sample_rate);
+            let ay = Rc::new(RefCell::new(ay));
+            self.cpu.borrow_mut().interconnect_mut().add_ay(ay.clone());
+            self.ays.push(ay);
+        }
+    }
+
+    // Fit the Cheetah Specdrum drum machine, an 8 bit DAC on port 0xDF
+    pub fn enable_specdrum(&mut self) {
+        self.add_dac(SPECDRUM_PORT);
+    }
+
+    // Fit a Covox, an 8 bit DAC on port 0xFB
+    pub fn enable_covox(&mut self) {
+        self.add_dac(COVOX_PORT);
+    }
+
+    fn add_dac(&mut self, port: u16) {
+        let mut dac = Dac::new();
+        if let Some(hz) = self.clock.hz() {
+            dac.set_clock(hz as u32);
         }
+        dac.set_sample_rate(self.sample_rate);
+        let dac = Rc::new(RefCell::new(dac));
+        self.cpu.borrow_mut().interconnect_mut().attach(dac.clone(), DAC_PORT_MASK, port);
+        self.dacs.push(dac);
     }
 
-    pub fn run(&self) {
+    // Put a tape in, wound to the start. Nothing plays until the ROM's
+    // loader asks for it or play_tape is called.
+    pub fn insert_tape(&mut self, tape: Tape) {
+        self.eject_tape();
+        let mut player = TapePlayer::new(tape);
+        player.set_48k(self.model != Model::Spectrum128K);
+        if let Some(hz) = self.clock.hz() {
+            player.set_clock(hz as u32);
+        }
+        self.tape = Some(player);
+    }
+
+    pub fn load_tape<P: AsRef<Path>>(&mut self, path: P) -> Result<(), String> {
+        let tape = Tape::load(path)?;
+        self.insert_tape(tape);
+        Ok(())
+    }
+
+    pub fn eject_tape(&mut self) {
+        self.tape = None;
+        self.ula.borrow_mut().set_ear_input(None);
+    }
+
+    pub fn tape(&self) -> Option<&TapePlayer> {
+        self.tape.as_ref()
+    }
+
+    // Fast load copies each block straight into memory when the ROM's
+    // LD-BYTES is called, otherwise the tape is played in real time
+    pub fn set_tape_fast_load(&mut self, val: bool) {
+        self.fast_load = val;
+    }
+
+    pub fn play_tape(&mut self) {
+        let tcycles = self.cpu.borrow().tcycles;
+        let frame_tcycles = self.cpu.borrow().get_frame_tcycles();
+        if let Some(ref mut tape) = self.tape {
+            tape.play();
+            if tape.is_playing() {
+                // Play the rest of this frame now, later frames get their
+                // signal as they start
+                let mut ula = self.ula.borrow_mut();
+                ula.set_ear_input(Some(tape.level()));
+                let left = frame_tcycles.saturating_sub(tcycles).min(self.timing.frame_tcycles);
+                for (t, level) in tape.run(left) {
+                    ula.add_ear_edge(tcycles + t, level);
+                }
+            }
+        }
+    }
+
+    pub fn stop_tape(&mut self) {
+        if let Some(ref mut tape) = self.tape {
+            tape.stop();
+        }
+        self.ula.borrow_mut().set_ear_input(None);
+    }
+
+    pub fn is_tape_playing(&self) -> bool {
+        self.tape.as_ref().map_or(false, |tape| tape.is_playing())
+    }
+
+    // Block the tape is at, out of how many there are
+    pub fn tape_position(&self) -> Option<(usize, usize)> {
+        self.tape.as_ref().map(|tape| (tape.block(), tape.tape().len()))
+    }
+
+    pub fn set_tape_block(&mut self, block: usize) {
+        if let Some(ref mut tape) = self.tape {
+            tape.set_block(block);
+        }
+    }
+
+    pub fn rewind_tape(&mut self) {
+        self.set_tape_block(0);
+    }
+
+    // Signal of the tape for the frame about to start
+    fn feed_tape(&mut self, frame_tcycles: u32) {
+        let mut ula = self.ula.borrow_mut();
+        match self.tape {
+            Some(ref mut tape) if tape.is_playing() => {
+                for (t, level) in tape.run(frame_tcycles) {
+                    ula.add_ear_edge(t, level);
+                }
+            }
+            _ => {
+                if ula.get_ear_input().is_some() {
+                    ula.set_ear_input(None);
+                }
+            }
+        }
+    }
+
+    // The ROM's LD-BYTES routine is about to run: with fast load, do what
+    // it would with the next block and return to the caller, otherwise
+    // start the tape
+    fn trap_tape(&mut self) {
+        // The 48 BASIC ROM is the second one on the 128K
+        let basic_rom = if self.model == Model::Spectrum128K { 1 } else { 0 };
+        if self.cpu.borrow().get_pc() != LD_BYTES ||
+           self.memory.borrow().get_0000_bank() != basic_rom {
+            return;
+        }
+        if !self.fast_load {
+            if !self.is_tape_playing() && !self.tape.as_ref().map_or(true, |tape| tape.is_at_end()) {
+                self.play_tape();
+            }
+            return;
+        }
+        // With no block left the ROM waits for one, and BREAK still works
+        let data = match self.tape.as_mut().and_then(|tape| tape.take_data_block()) {
+            Some(data) => data,
+            None => return,
+        };
+
+        let mut cpu = self.cpu.borrow_mut();
+        let mut memory = self.memory.borrow_mut();
+        // A holds the flag byte expected, IX and DE where the data goes and
+        // how long it is. Carry is set to load, reset to verify.
+        let flag = cpu.read_reg8(Reg8::A);
+        let verify = !cpu.get_flag(CARRY_FLAG);
+        let mut addr = cpu.read_reg16(Reg16::IX);
+        let mut length = cpu.read_reg16(Reg16::DE);
+
+        let mut ok = data.first() == Some(&flag);
+        if ok {
+            let mut parity = flag;
+            let mut bytes = data[1..].iter();
+            while length > 0 {
+                let byte = match bytes.next() {
+                    Some(&byte) => byte,
+                    None => break,
+                };
+                parity ^= byte;
+                if verify {
+                    if memory.read_word(addr) != byte {
+                        break;
+                    }
+                } else {
+                    memory.write_word(addr, byte);
+                }
+                addr = addr.wrapping_add(1);
+                length -= 1;
+            }
+            // Then the checksum
+            ok = length == 0 && bytes.next().map_or(false, |&byte| parity ^ byte == 0);
+        }
+
+        cpu.write_reg16(Reg16::IX, addr);
+        cpu.write_reg16(Reg16::DE, length);
+        cpu.cond_flag(CARRY_FLAG, ok);
+        // SA/LD-RET enables interrupts on the way out
+        cpu.set_iff1();
+        cpu.set_iff2();
+        let sp = cpu.read_reg16(Reg16::SP);
+        let ret = memory.read_word(sp) as u16 | (memory.read_word(sp.wrapping_add(1)) as u16) << 8;
+        cpu.write_reg16(Reg16::SP, sp.wrapping_add(2));
+        cpu.set_pc(ret);
+    }
+
+    // Log the register writes to the (first) AY, to save them as music
+    pub fn start_ay_log(&mut self) -> Result<(), String> {
+        match self.ays.first() {
+            Some(ay) => {
+                ay.borrow_mut().start_log();
+                Ok(())
+            }
+            None => Err("This machine has no AY".to_string()),
+        }
+    }
+
+    // Stop logging and save the log as .psg, or else .ym
+    pub fn save_ay_log<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let log = self.ays.first().and_then(|ay| ay.borrow_mut().take_log());
+        match log {
+            Some(log) => log.save(path),
+            None => Err(io::Error::new(io::ErrorKind::Other, "The AY log was never started")),
+        }
+    }
+
+    // Sound produced since the last call, as interleaved left and right
+    // samples
+    pub fn audio_samples(&mut self) -> Vec<i16> {
+        ::std::mem::replace(&mut self.audio, Vec::new())
+    }
+
+    // Save everything played from now on as a WAV file
+    pub fn start_audio_recording<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let out = BufWriter::new(File::create(path)?);
+        self.recorder = Some(WavWriter::new(out, self.sample_rate, 2)?);
+        Ok(())
+    }
+
+    pub fn stop_audio_recording(&mut self) -> io::Result<()> {
+        match self.recorder.take() {
+            Some(recorder) => recorder.finish().map(|_| ()),
+            None => Ok(()),
+        }
+    }
+
+    fn mix_audio(&mut self) {
+        let beeper = self.ula.borrow_mut().beeper_mut().take_samples();
+        let ays: Vec<Vec<[u16; 3]>> = self.ays.iter()
+            .map(|ay| ay.borrow_mut().take_samples())
+            .collect();
+        let dacs: Vec<Vec<u16>> = self.dacs.iter()
+            .map(|dac| dac.borrow_mut().take_samples())
+            .collect();
+        let samples = self.mixer.mix(&beeper, &ays, &dacs);
+
+        if let Some(ref mut recorder) = self.recorder {
+            recorder.write_samples(&samples).unwrap();
+        }
+        self.audio.extend(samples);
+    }
+
+    // Copy bytes into memory as currently paged in
+    pub fn load_bytes(&mut self, addr: u16, bytes: &[u8]) {
+        let mut memory = self.memory.borrow_mut();
+        for (i, byte) in bytes.iter().enumerate() {
+            memory.write_word(addr.wrapping_add(i as u16), *byte);
+        }
+    }
+
+    // Load a .z80 snapshot into the running machine. A 128K pages in the
+    // 48 BASIC ROM to run it. A .z80 has nowhere to keep the ULAplus state,
+    // so ULAplus starts again from the standard palette.
+    pub fn load_snapshot(&mut self, snapshot: &[u8]) -> Result<(), String> {
+        let (header, data) = match parse(snapshot) {
+            Some(snapshot) => snapshot,
+            None => return Err("Not a valid .z80 snapshot".to_string()),
+        };
+        let banks = snapshot_banks(self.ram_size, data)?;
+
+        self.cpu.borrow().interconnect().write_port(0x7ffd, 0x10, 0);
+        for (bank, addr) in banks.iter().zip([0x4000u16, 0x8000, 0xC000].iter()) {
+            self.load_bytes(*addr, bank);
+        }
+        self.load_registers(&header);
+        if self.ula.borrow().has_ulaplus() {
+            self.set_ulaplus(UlaPlus::new());
+        }
+        Ok(())
+    }
+
+    fn load_registers(&self, header: &Z80Header) {
+        let mut cpu = self.cpu.borrow_mut();
+        cpu.write_reg16(Reg16::AF, header.af);
+        cpu.write_reg16(Reg16::BC, header.bc);
+        cpu.write_reg16(Reg16::HL, header.hl);
+        cpu.set_pc(header.pc);
+        cpu.write_reg16(Reg16::SP, header.sp);
+        cpu.write_reg8(Reg8::I, header.ir);
+        cpu.write_reg8(Reg8::R, header.r);
+        cpu.write_reg16(Reg16::DE, header.de);
+        cpu.write_reg16(Reg16::BC_ALT, header.bc_alt);
+        cpu.write_reg16(Reg16::DE_ALT, header.de_alt);
+        cpu.write_reg16(Reg16::HL_ALT, header.hl_alt);
+        cpu.write_reg16(Reg16::AF_ALT, header.af_alt);
+        cpu.write_reg16(Reg16::IX, header.ix);
+        cpu.write_reg16(Reg16::IY, header.iy);
+        if header.iff1 { cpu.set_iff1() } else { cpu.clear_iff1() }
+        if header.iff2 { cpu.set_iff2() } else { cpu.clear_iff2() }
+        cpu.set_im(header.misc2 & 0x03);
+    }
+
+    // Run one instruction, and the interrupt before it if one is due, and
+    // return the T states that took
+    fn step(&mut self) -> u32 {
+        if self.tape.is_some() {
+            self.trap_tape();
+        }
+        let mut cpu = self.cpu.borrow_mut();
+        let mut start = cpu.tcycles;
+        cpu.handle_interrupts();
+        // A new frame started, T states count from its beginning again
+        if cpu.tcycles < start {
+            start -= cpu.get_frame_tcycles();
+        }
+        cpu.run_instruction();
+        cpu.tcycles - start
+    }
+
+    fn frame_ended(&self) -> bool {
+        let mut cpu = self.cpu.borrow_mut();
+        // Without a wall clock to follow, unlimited mode uses frames of the
+        // usual length
+        if self.clock == CpuClock::Unlimited && cpu.tcycles >= self.timing.frame_tcycles {
+            cpu.end_frame();
+        }
+        cpu.tcycles >= cpu.get_frame_tcycles()
+    }
+
+    // Finish the frame for everything that keeps track of time within it
+    fn end_frame(&mut self) {
+        let frame_tcycles = self.clock.frame_tcycles_for(&self.timing)
+            .unwrap_or(self.timing.frame_tcycles);
+        self.ula.borrow_mut().beeper_mut().end_frame(frame_tcycles);
+        for ay in &self.ays {
+            ay.borrow_mut().end_frame(frame_tcycles);
+        }
+        for dac in &self.dacs {
+            dac.borrow_mut().end_frame(frame_tcycles);
+        }
+        self.mix_audio();
+        self.update_framebuffer();
+        self.feed_tape(frame_tcycles);
+    }
+
+    // Run up to the end of the current frame and draw it
+    pub fn run_frame(&mut self) {
+        loop {
+            self.step();
+            if self.frame_ended() {
+                break;
+            }
+        }
+        self.end_frame();
+    }
+
+    // Run for at least n T states, drawing every frame that ends on the way
+    pub fn run_tstates(&mut self, n: u32) {
+        let mut elapsed = 0;
+        while elapsed < n {
+            elapsed += self.step();
+            if self.frame_ended() {
+                self.end_frame();
+            }
+        }
+    }
+
+    #[cfg(feature = "sdl")]
+    pub fn run(&mut self) {
         let mut debugger = Debugger::new(
             self.cpu.clone(),
             self.memory.clone());
@@ -229,7 +918,9 @@ impl Machine {
         let sdl_context = sdl2::init().unwrap();
         let video_subsystem = sdl_context.video().unwrap();
 
-        let window = video_subsystem.window("rustz80emu", 512, 384)
+        let window = video_subsystem.window("rustz80emu",
+                                            2 * DISPLAY_WIDTH as u32,
+                                            2 * DISPLAY_HEIGHT as u32)
             .position_centered()
             .build()
             .unwrap();
@@ -238,16 +929,62 @@ impl Machine {
 
         let texture_creator = canvas.texture_creator();
         let mut texture = texture_creator.create_texture_streaming(
-            PixelFormatEnum::RGB24, 256, 192).unwrap();
+            PixelFormatEnum::RGB24, DISPLAY_WIDTH as u32, DISPLAY_HEIGHT as u32).unwrap();
 
         let mut event_pump = sdl_context.event_pump().unwrap();
 
+        let audio: Option<AudioQueue<i16>> = if self.mute {
+            None
+        } else {
+            let audio_subsystem = sdl_context.audio().unwrap();
+            let desired = AudioSpecDesired {
+                freq: Some(44100),
+                channels: Some(2),
+                samples: Some(512),
+            };
+            let queue = audio_subsystem.open_queue(None, &desired).unwrap();
+            self.set_sample_rate(queue.spec().freq as u32);
+            queue.resume();
+            Some(queue)
+        };
+        // Three frames of sound queued up
+        let mut feeder = AudioFeeder::new(self.samples_per_frame() * 3, 2);
+
+        let frame_duration = self.frame_duration();
+        let mut pacer = FramePacer::new(SystemClock::new(), frame_duration);
+        pacer.set_speed(self.speed);
+
+        let mut frame_start = Instant::now();
+        let mut instructions: u32 = 0;
+
         'machine: loop {
             for event in event_pump.poll_iter() {
                 match event {
                     Event::Quit {..} => {
                         break 'machine
                     },
+                    // Held down to run as fast as possible
+                    Event::KeyDown { keycode:Some(Keycode::F12), ..} => {
+                        pacer.set_fast_forward(true);
+                    },
+                    Event::KeyUp { keycode:Some(Keycode::F12), ..} => {
+                        pacer.set_fast_forward(false);
+                    },
+                    // Press play or stop on the tape
+                    Event::KeyDown { keycode:Some(Keycode::F8), repeat:false, ..} => {
+                        if self.is_tape_playing() {
+                            self.stop_tape();
+                        } else {
+                            self.play_tape();
+                        }
+                    },
+                    Event::KeyDown { keycode:Some(Keycode::F9), repeat:false, ..} => {
+                        pacer.toggle_pause();
+                    },
+                    // Run a single frame while paused
+                    Event::KeyDown { keycode:Some(Keycode::F10), ..} => {
+                        pacer.advance_frame();
+                    },
                     Event::KeyDown { keycode:Some(k), ..} => {
                         match KEYBOARD_MAPPINGS.get(&k) {
                             Some(&(ref key1, ref key2)) => {
@@ -270,20 +1007,56 @@ impl Machine {
                 }
             }
 
-            if self.debug_on { debugger.pre(); }
+            if !pacer.frame_due() {
+                continue;
+            }
+
+            loop {
+                if self.debug_on { debugger.pre(); }
+
+                self.step();
 
-            if self.cpu.borrow().tcycles > 70908 {
-                self.ula.borrow().display(&mut texture);
+                if self.debug_on { debugger.post(); }
+
+                // Checking the time is costly, so only do it every so often
+                if self.clock == CpuClock::Unlimited {
+                    instructions = instructions.wrapping_add(1);
+                    if instructions % 1024 == 0 && frame_start.elapsed() >= frame_duration {
+                        self.cpu.borrow_mut().end_frame();
+                        frame_start = Instant::now();
+                    }
+                }
 
-                canvas.clear();
-                canvas.copy(&texture, None, Some(Rect::new(0, 0, 512, 384))).unwrap();
-                canvas.present();
+                if self.cpu.borrow().tcycles >= self.cpu.borrow().get_frame_tcycles() {
+                    break;
+                }
             }
 
-            self.cpu.borrow_mut().handle_interrupts();
-            self.cpu.borrow_mut().run_instruction();
+            self.end_frame();
+            texture.update(None, self.framebuffer.data(), self.framebuffer.pitch()).unwrap();
+
+            canvas.clear();
+            canvas.copy(&texture, None, Some(Rect::new(0, 0,
+                                                       2 * DISPLAY_WIDTH as u32,
+                                                       2 * DISPLAY_HEIGHT as u32))).unwrap();
+            canvas.present();
 
-            if self.debug_on { debugger.post(); }
+            let samples = self.audio_samples();
+            match audio {
+                Some(ref queue) => {
+                    let queued = queue.size() as usize / 4;
+                    queue.queue(&feeder.feed(queued, &samples));
+
+                    if self.audio_sync && !pacer.is_fast_forward() {
+                        while feeder.is_ahead(queue.size() as usize / 4) {
+                            thread::sleep(Duration::from_millis(1));
+                        }
+                    } else {
+                        pacer.end_frame();
+                    }
+                }
+                None => pacer.end_frame(),
+            }
         }
     }
 }
@@ -2,10 +2,12 @@ use std::path::Path;
 
 extern crate z80emulib;
 use z80emulib::machine::*;
+use z80emulib::mixer::StereoMode;
+use z80emulib::tape::Tape;
 use z80emulib::utils::read_bin;
 
 extern crate getopts;
-use getopts::Options;
+use getopts::{Matches, Options};
 use std::env;
 
 fn main() {
@@ -25,6 +27,103 @@ fn main() {
         "snapshot",
         "Load a snapshot instead of booting from the default ROMs",
         "PATH");
+    opts.optopt(
+        "c",
+        "clock",
+        "CPU clock in MHz: 3.5, 3.5469, 7, 14 or unlimited (default: the model's own)",
+        "MHZ");
+    opts.optopt(
+        "",
+        "speed",
+        "Emulation speed as a percentage of the real machine (default 100). \
+         Hold F12 to fast-forward, F9 pauses and F10 advances a frame",
+        "PERCENT");
+    opts.optflag(
+        "",
+        "mute",
+        "Don't play any sound");
+    opts.optflag(
+        "",
+        "audio-sync",
+        "Run the emulation as fast as the sound plays instead of following the wall clock");
+    opts.optopt(
+        "",
+        "record-audio",
+        "Save the sound as a WAV file",
+        "PATH");
+    opts.optopt(
+        "",
+        "stereo",
+        "AY channel layout: mono (default), abc or acb",
+        "MODE");
+    opts.optopt(
+        "",
+        "separation",
+        "How far apart the stereo channels are, in percent (default 100)",
+        "PERCENT");
+    opts.optflag(
+        "",
+        "turbosound",
+        "Fit a second AY, selected by writing 0xFE or 0xFF to port 0xFFFD");
+    opts.optopt(
+        "",
+        "ay-log",
+        "Save the AY register writes as music, as .psg or else .ym",
+        "PATH");
+    opts.optflag(
+        "",
+        "specdrum",
+        "Fit a Cheetah Specdrum, an 8 bit DAC on port 0xDF");
+    opts.optflag(
+        "",
+        "covox",
+        "Fit a Covox, an 8 bit DAC on port 0xFB");
+    opts.optopt(
+        "",
+        "tape",
+        "Insert a .tap or .tzx tape image, loaded as LOAD \"\" asks for it",
+        "PATH");
+    opts.optflag(
+        "",
+        "list-tape",
+        "List the blocks of the tape given by --tape and exit");
+    opts.optflag(
+        "",
+        "tape-realtime",
+        "Play the tape into the EAR input instead of loading blocks straight into memory");
+    opts.optopt(
+        "",
+        "issue",
+        "Board issue, for what port 0xFE bit 6 reads without a tape: 2 or 3 (default)",
+        "ISSUE");
+    opts.optflag(
+        "",
+        "ulaplus",
+        "Fit the ULAplus 64 colour palette extension");
+    opts.optflag(
+        "",
+        "headless",
+        "Run without a window, for the number of frames given by --frames");
+    opts.optopt(
+        "",
+        "frames",
+        "Number of frames to run in headless mode (default 100)",
+        "N");
+    opts.optopt(
+        "",
+        "screenshot",
+        "Save the last frame when done, as PNG or else PPM depending on the extension",
+        "PATH");
+    opts.optopt(
+        "m",
+        "model",
+        "Spectrum model: 16, 48 or 128 (default, 48 for snapshots)",
+        "MODEL");
+    opts.optopt(
+        "r",
+        "ram",
+        "RAM size in KB, 16 to 48 on the 48K model",
+        "KB");
 
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => { m }
@@ -37,21 +136,140 @@ fn main() {
         return;
     }
 
+    if matches.opt_present("list-tape") {
+        let tape_path = matches.opt_str("tape").expect("--list-tape needs --tape");
+        let tape = Tape::load(&tape_path).unwrap();
+        for (i, block) in tape.blocks().iter().enumerate() {
+            println!("{:3}: {}", i, block.describe());
+        }
+        return;
+    }
+
     let mut start_in_debug = false;
     if matches.opt_present("d") {
         start_in_debug = true;
     }
 
+    let model = match matches.opt_str("m") {
+        Some(model) => model.parse().unwrap(),
+        None if matches.opt_present("s") => Model::Spectrum48K,
+        None => Model::Spectrum128K,
+    };
+
+    let ram_size = match matches.opt_str("r") {
+        Some(ram_size) => ram_size.parse().unwrap(),
+        None => model.ram_size(),
+    };
+    if !model.supports_ram_size(ram_size) {
+        panic!("{:?} can't have {}K of RAM", model, ram_size);
+    }
+
+    let clock = match matches.opt_str("c") {
+        Some(clock) => clock.parse().unwrap(),
+        None => model.native_clock(),
+    };
+
+    let speed = match matches.opt_str("speed") {
+        Some(speed) => speed.parse().unwrap(),
+        None => 100,
+    };
+
     if let Some(snapshot_path) = matches.opt_str("s") {
         let snapshot_file = read_bin(Path::new(&snapshot_path));
         if let Some((header, data)) = z80emulib::snapshot::parse(&snapshot_file[..]) {
-            let machine = Machine::from_snapshot(start_in_debug, &header, data);
+            let mut machine = match Machine::from_snapshot(start_in_debug, model, ram_size, &header, data) {
+                Ok(machine) => machine,
+                Err(e) => panic!(e),
+            };
+            machine.set_cpu_clock(clock);
+            machine.set_speed(speed);
+            machine.set_mute(matches.opt_present("mute"));
+            machine.set_audio_sync(matches.opt_present("audio-sync"));
+            if matches.opt_present("ulaplus") {
+                machine.enable_ulaplus();
+            }
+
+            run(machine, &matches);
+        }
+    } else {
+        let mut machine = Machine::new(start_in_debug, model, ram_size);
+        machine.set_cpu_clock(clock);
+        machine.set_speed(speed);
+        machine.set_mute(matches.opt_present("mute"));
+        machine.set_audio_sync(matches.opt_present("audio-sync"));
+        if matches.opt_present("ulaplus") {
+            machine.enable_ulaplus();
+        }
+
+        run(machine, &matches);
+    }
+}
+
+fn run(mut machine: Machine, matches: &Matches) {
+    let stereo = match matches.opt_str("stereo") {
+        Some(stereo) => stereo.parse().unwrap(),
+        None => StereoMode::Mono,
+    };
+    let separation: u32 = match matches.opt_str("separation") {
+        Some(separation) => separation.parse().unwrap(),
+        None => 100,
+    };
+    machine.set_stereo_mode(stereo, separation as f32 / 100.0);
+    if let Some(issue) = matches.opt_str("issue") {
+        machine.set_issue(issue.parse().unwrap());
+    }
+    if matches.opt_present("turbosound") {
+        machine.enable_turbosound();
+    }
+    if matches.opt_present("specdrum") {
+        machine.enable_specdrum();
+    }
+    if matches.opt_present("covox") {
+        machine.enable_covox();
+    }
+
+    if let Some(tape_path) = matches.opt_str("tape") {
+        machine.load_tape(&tape_path).unwrap();
+        machine.set_tape_fast_load(!matches.opt_present("tape-realtime"));
+    }
+
+    if let Some(audio_path) = matches.opt_str("record-audio") {
+        machine.start_audio_recording(&audio_path).unwrap();
+    }
+    if matches.opt_present("ay-log") {
+        machine.start_ay_log().unwrap();
+    }
+
+    if matches.opt_present("headless") {
+        let frames: u32 = match matches.opt_str("frames") {
+            Some(frames) => frames.parse().unwrap(),
+            None => 100,
+        };
+        for _ in 0..frames {
+            machine.run_frame();
+            // Nothing plays the sound
+            machine.audio_samples();
+        }
 
-            machine.run();
+        if let Some(screenshot_path) = matches.opt_str("screenshot") {
+            machine.framebuffer().save(&screenshot_path).unwrap();
         }
     } else {
-        let machine = Machine::new(start_in_debug);
+        run_window(&mut machine);
+    }
 
-        machine.run();
+    machine.stop_audio_recording().unwrap();
+    if let Some(ay_log_path) = matches.opt_str("ay-log") {
+        machine.save_ay_log(&ay_log_path).unwrap();
     }
 }
+
+#[cfg(feature = "sdl")]
+fn run_window(machine: &mut Machine) {
+    machine.run();
+}
+
+#[cfg(not(feature = "sdl"))]
+fn run_window(_: &mut Machine) {
+    panic!("Built without SDL, only --headless is available");
+}
@@ -0,0 +1,91 @@
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StereoMode {
+    Mono,
+    // Channel A on the left, B in the middle and C on the right
+    Abc,
+    Acb,
+}
+
+impl StereoMode {
+    // Position of channels A, B and C, from -1 (left) to 1 (right)
+    pub fn panning(&self, separation: f32) -> [f32; 3] {
+        match *self {
+            StereoMode::Mono => [0.0, 0.0, 0.0],
+            StereoMode::Abc  => [-separation, 0.0, separation],
+            StereoMode::Acb  => [-separation, separation, 0.0],
+        }
+    }
+}
+
+impl FromStr for StereoMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mono" => Ok(StereoMode::Mono),
+            "abc"  => Ok(StereoMode::Abc),
+            "acb"  => Ok(StereoMode::Acb),
+            _ => Err(format!("Unknown stereo mode: {}", s)),
+        }
+    }
+}
+
+// Brings the beeper, every AY channel and any DACs together into interleaved
+// stereo. A source in the middle is at full volume on both sides, one panned
+// away from a side fades out of it. Only AY channels are panned.
+pub struct Mixer {
+    panning: [f32; 3],
+}
+
+impl Mixer {
+    pub fn new() -> Self {
+        Mixer { panning: [0.0; 3] }
+    }
+
+    pub fn set_stereo_mode(&mut self, mode: StereoMode, separation: f32) {
+        self.set_panning(mode.panning(separation));
+    }
+
+    pub fn set_panning(&mut self, panning: [f32; 3]) {
+        for pan in panning.iter() {
+            assert!(*pan >= -1.0 && *pan <= 1.0, "Panning out of range: {}", pan);
+        }
+        self.panning = panning;
+    }
+
+    pub fn get_panning(&self) -> [f32; 3] {
+        self.panning
+    }
+
+    // Both sides at full scale only when every source is. A source that
+    // comes up short holds its last level.
+    pub fn mix(&self, beeper: &[u16], ays: &[Vec<[u16; 3]>], dacs: &[Vec<u16>]) -> Vec<i16> {
+        let scale = 1.0 / (2 * (1 + 3 * ays.len() + dacs.len())) as f32;
+        let weights: Vec<(f32, f32)> = self.panning.iter()
+            .map(|&pan| ((1.0 - pan).min(1.0), (1.0 + pan).min(1.0)))
+            .collect();
+
+        let mut out = Vec::with_capacity(beeper.len() * 2);
+        for (i, &level) in beeper.iter().enumerate() {
+            let mut left = level as f32;
+            let mut right = level as f32;
+            for ay in ays {
+                let levels = ay.get(i).or(ay.last()).cloned().unwrap_or([0; 3]);
+                for channel in 0..3 {
+                    left += levels[channel] as f32 * weights[channel].0;
+                    right += levels[channel] as f32 * weights[channel].1;
+                }
+            }
+            for dac in dacs {
+                let level = dac.get(i).or(dac.last()).cloned().unwrap_or(0) as f32;
+                left += level;
+                right += level;
+            }
+            out.push((left * scale) as i16);
+            out.push((right * scale) as i16);
+        }
+        out
+    }
+}
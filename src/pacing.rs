@@ -0,0 +1,188 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Where the pacer gets the time from, so that tests can fake it
+pub trait Clock {
+    // Time elapsed since some fixed point
+    fn now(&self) -> Duration;
+    fn sleep(&mut self, duration: Duration);
+}
+
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        SystemClock { start: Instant::now() }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+// Falling further behind than this many frames gives up on catching up
+const MAX_FRAMES_BEHIND: u32 = 5;
+
+// Keeps emulated frames in step with real time. Each frame has a deadline
+// one frame period after the previous one, so sleeping too long on one frame
+// is made up for on the next.
+pub struct FramePacer<C: Clock> {
+    clock: C,
+    frame_duration: Duration,
+    speed: u32,
+
+    fast_forward: bool,
+    paused: bool,
+    // Frames to run while paused
+    advance: u32,
+
+    deadline: Option<Duration>,
+}
+
+impl<C: Clock> FramePacer<C> {
+    pub fn new(clock: C, frame_duration: Duration) -> Self {
+        FramePacer {
+            clock,
+            frame_duration,
+            speed: 100,
+            fast_forward: false,
+            paused: false,
+            advance: 0,
+            deadline: None,
+        }
+    }
+
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    pub fn clock_mut(&mut self) -> &mut C {
+        &mut self.clock
+    }
+
+    // Speed as a percentage of the real machine
+    pub fn set_speed(&mut self, percent: u32) {
+        assert!(percent > 0, "Speed must be above 0%");
+        self.speed = percent;
+    }
+
+    pub fn set_fast_forward(&mut self, val: bool) {
+        self.fast_forward = val;
+    }
+
+    pub fn is_fast_forward(&self) -> bool {
+        self.fast_forward
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.advance = 0;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    // Run a single frame while paused
+    pub fn advance_frame(&mut self) {
+        if self.paused {
+            self.advance += 1;
+        }
+    }
+
+    fn period(&self) -> Duration {
+        self.frame_duration * 100 / self.speed
+    }
+
+    // Whether the next frame should be emulated now. When it shouldn't, the
+    // pacer idles for a frame period so that the caller can poll for input.
+    pub fn frame_due(&mut self) -> bool {
+        if !self.paused {
+            return true;
+        }
+        if self.advance > 0 {
+            self.advance -= 1;
+            return true;
+        }
+
+        let period = self.period();
+        self.clock.sleep(period);
+        self.deadline = None;
+        false
+    }
+
+    // Called once a frame has been emulated, waits until it is due
+    pub fn end_frame(&mut self) {
+        if self.fast_forward {
+            self.deadline = None;
+            return;
+        }
+
+        let now = self.clock.now();
+        let period = self.period();
+        let deadline = match self.deadline {
+            Some(deadline) if now <= deadline + period * MAX_FRAMES_BEHIND => deadline + period,
+            _ => now + period,
+        };
+
+        if deadline > now {
+            self.clock.sleep(deadline - now);
+        }
+        self.deadline = Some(deadline);
+    }
+}
+
+// Keeps the audio queue of a front-end around a target number of sample
+// frames, one sample per channel each. When it runs dry it is refilled with
+// the last frame held, so that the gap is silent, and when it would overfill,
+// frames are evenly dropped.
+pub struct AudioFeeder {
+    target: usize,
+    channels: usize,
+    last: Vec<i16>,
+}
+
+impl AudioFeeder {
+    pub fn new(target: usize, channels: usize) -> Self {
+        AudioFeeder { target, channels, last: vec![0; channels] }
+    }
+
+    // Interleaved samples to add to a queue still holding queued frames
+    pub fn feed(&mut self, queued: usize, samples: &[i16]) -> Vec<i16> {
+        let mut out = Vec::with_capacity((self.target + samples.len()) * self.channels);
+        if queued == 0 {
+            for _ in 0..self.target / 2 {
+                out.extend_from_slice(&self.last);
+            }
+        }
+
+        let frames: Vec<&[i16]> = samples.chunks(self.channels).collect();
+        let room = (2 * self.target).saturating_sub(queued + out.len() / self.channels);
+        if frames.len() <= room {
+            out.extend_from_slice(samples);
+        } else {
+            for i in 0..room {
+                out.extend_from_slice(frames[i * frames.len() / room]);
+            }
+        }
+
+        if out.len() >= self.channels {
+            let last = out.len() - self.channels;
+            self.last = out[last..].to_vec();
+        }
+        out
+    }
+
+    // When emulation follows the audio, it waits while this is true
+    pub fn is_ahead(&self, queued: usize) -> bool {
+        queued > self.target
+    }
+}
@@ -0,0 +1,128 @@
+use super::Peripheral;
+
+use std::io;
+use std::io::{Read, Write};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+
+bitflags! {
+    pub struct AciaStatus: u8 {
+        const RX_DATA_REGISTER_FULL  = 0b00000001; // RDRF
+        const TX_DATA_REGISTER_EMPTY = 0b00000010; // TDRE
+        const DATA_CARRIER_DETECT    = 0b00000100; // DCD
+        const CLEAR_TO_SEND          = 0b00001000; // CTS
+        const FRAMING_ERROR          = 0b00010000; // FE
+        const RECEIVER_OVERRUN       = 0b00100000; // OVRN
+        const PARITY_ERROR           = 0b01000000; // PE
+        const INTERRUPT_REQUEST      = 0b10000000; // IRQ
+    }
+}
+
+const CONTROL_MASTER_RESET: u8 = 0b00000011;
+const CONTROL_TX_INTERRUPT_MASK: u8 = 0b01100000;
+const CONTROL_TX_INTERRUPT_ENABLED: u8 = 0b00100000;
+const CONTROL_RX_INTERRUPT_ENABLED: u8 = 0b10000000;
+
+// Motorola MC6850 ACIA. Even ports address the control/status register and
+// odd ports the transmit/receive data registers. The serial line is
+// connected to a byte channel for input and any writer for output, and
+// transmission is instantaneous, so the transmit register is always empty.
+pub struct Acia {
+    control: u8,
+    rx_data: u8,
+    rx_full: bool,
+
+    input: Receiver<u8>,
+    output: Box<dyn Write>,
+}
+
+impl Acia {
+    pub fn new(input: Receiver<u8>, output: Box<dyn Write>) -> Self {
+        Acia {
+            control: CONTROL_MASTER_RESET,
+            rx_data: 0,
+            rx_full: false,
+            input,
+            output,
+        }
+    }
+
+    // Serial line wired to the host terminal. Stdin is read on its own
+    // thread so that polling the ACIA never blocks the emulation.
+    pub fn with_stdio() -> Self {
+        let (sender, receiver) = channel();
+        thread::spawn(move || {
+            for byte in io::stdin().bytes() {
+                let byte = match byte {
+                    Ok(b'\n') => b'\r',
+                    Ok(byte) => byte,
+                    Err(_) => break,
+                };
+                if sender.send(byte).is_err() { break; }
+            }
+        });
+        Acia::new(receiver, Box::new(io::stdout()))
+    }
+
+    pub fn status(&mut self) -> AciaStatus {
+        self.receive();
+
+        let mut status = TX_DATA_REGISTER_EMPTY;
+        if self.rx_full {
+            status.insert(RX_DATA_REGISTER_FULL);
+        }
+        if self.irq() {
+            status.insert(INTERRUPT_REQUEST);
+        }
+        status
+    }
+
+    fn in_reset(&self) -> bool {
+        self.control & CONTROL_MASTER_RESET == CONTROL_MASTER_RESET
+    }
+
+    fn receive(&mut self) {
+        if self.rx_full || self.in_reset() { return; }
+
+        if let Ok(byte) = self.input.try_recv() {
+            self.rx_data = byte;
+            self.rx_full = true;
+        }
+    }
+
+    fn irq(&self) -> bool {
+        if self.in_reset() { return false; }
+
+        let rx_irq = self.control & CONTROL_RX_INTERRUPT_ENABLED != 0 && self.rx_full;
+        let tx_irq = self.control & CONTROL_TX_INTERRUPT_MASK == CONTROL_TX_INTERRUPT_ENABLED;
+        rx_irq || tx_irq
+    }
+}
+
+impl Peripheral for Acia {
+    fn read_port(&mut self, port: u16) -> u8 {
+        if port & 0x0001 == 0 {
+            self.status().bits()
+        } else {
+            self.rx_full = false;
+            self.rx_data
+        }
+    }
+
+    fn write_port(&mut self, port: u16, val: u8) {
+        if port & 0x0001 == 0 {
+            self.control = val;
+            if self.in_reset() {
+                self.rx_full = false;
+            }
+        } else {
+            self.output.write_all(&[val]).unwrap();
+            self.output.flush().unwrap();
+        }
+    }
+
+    fn interrupt_pending(&mut self) -> bool {
+        self.receive();
+        self.irq()
+    }
+}
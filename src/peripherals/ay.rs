@@ -1,23 +1,305 @@
 use super::Peripheral;
+use aylog::AyLog;
+
+// Output level of each of the 16 volume steps, as measured on a real chip
+pub static AY_LEVELS: [u16; 16] = [
+    0x0000, 0x0385, 0x053D, 0x0770,
+    0x0AD7, 0x0FD5, 0x15B0, 0x230C,
+    0x2B4C, 0x43C1, 0x5A4B, 0x732F,
+    0x9204, 0xAFF1, 0xD921, 0xFFFF
+];
+
+// Bits of each register that exist on the chip, the rest read back as 0
+static REGISTER_MASKS: [u8; 16] = [
+    0xFF, 0x0F, 0xFF, 0x0F, 0xFF, 0x0F, 0x1F, 0xFF,
+    0x1F, 0x1F, 0x1F, 0xFF, 0xFF, 0x0F, 0xFF, 0xFF
+];
+
+const MIXER: usize = 7;
+const ENVELOPE_SHAPE: usize = 13;
+
+// Envelope shape bits
+const HOLD: u8 = 0x01;
+const ALTERNATE: u8 = 0x02;
+const ATTACK: u8 = 0x04;
+const CONTINUE: u8 = 0x08;
+
+pub const AY_SELECT_PORT: u16 = 0xfffd;
+pub const AY_DATA_PORT: u16 = 0xbffd;
 
 #[derive(RustcEncodable, RustcDecodable)]
 pub struct Ay {
-    value: u8,
+    registers: [u8; 16],
+    selected: usize,
+
+    cpu_hz: u32,
+    ay_hz: u32,
+    sample_rate: u32,
+
+    // T state of the current frame the chip has been run up to
+    tcycle: u32,
+    // Fractions of the next generator step and output sample, in CPU clock
+    // units
+    clock_rem: u32,
+    sample_rem: u32,
+
+    tone_counters: [u16; 3],
+    tone_outputs: [bool; 3],
+
+    noise_counter: u16,
+    noise_lfsr: u32,
+
+    envelope_counter: u32,
+    envelope_step: u8,
+    envelope_attack: bool,
+    envelope_holding: bool,
+
+    level_sums: [u32; 3],
+    level_count: u32,
+    samples: Vec<[u16; 3]>,
+
+    log: Option<AyLog>,
 }
 
 impl Ay {
     pub fn new() -> Self {
-        Ay { value: 0 }
+        Ay {
+            registers: [0; 16],
+            selected: 0,
+            cpu_hz: 3_546_900,
+            ay_hz: 1_773_450,
+            sample_rate: 44100,
+            tcycle: 0,
+            clock_rem: 0,
+            sample_rem: 0,
+            tone_counters: [0; 3],
+            tone_outputs: [false; 3],
+            noise_counter: 0,
+            noise_lfsr: 1,
+            envelope_counter: 0,
+            envelope_step: 0,
+            envelope_attack: false,
+            envelope_holding: true,
+            level_sums: [0; 3],
+            level_count: 0,
+            samples: Vec::new(),
+            log: None,
+        }
+    }
+
+    // The AY clock and the clock of the CPU whose T states it is given
+    pub fn set_clock(&mut self, ay_hz: u32, cpu_hz: u32) {
+        self.ay_hz = ay_hz;
+        self.cpu_hz = cpu_hz;
+        self.clock_rem = 0;
+        self.sample_rem = 0;
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.sample_rem = 0;
+    }
+
+    pub fn get_register(&self, register: usize) -> u8 {
+        self.registers[register]
+    }
+
+    pub fn set_register(&mut self, register: usize, val: u8) {
+        let register = register & 0x0F;
+        self.registers[register] = val & REGISTER_MASKS[register];
+        if register == ENVELOPE_SHAPE {
+            self.envelope_counter = 0;
+            self.envelope_step = 0;
+            self.envelope_attack = val & ATTACK != 0;
+            self.envelope_holding = false;
+        }
+    }
+
+    // Register writes carry the T state so that they take effect at the
+    // right point of the output
+    pub fn write_port_at(&mut self, port: u16, val: u8, tcycle: u32) {
+        self.catch_up(tcycle);
+        self.write_port(port, val);
+    }
+
+    // Generate the output up to tcycle
+    pub fn catch_up(&mut self, tcycle: u32) {
+        // The generators move every 8 AY clocks
+        let step = 8 * self.cpu_hz;
+        while self.tcycle < tcycle {
+            self.tcycle += 1;
+
+            self.clock_rem += self.ay_hz;
+            if self.clock_rem >= step {
+                self.clock_rem -= step;
+                self.step();
+            }
+
+            let levels = self.levels();
+            for channel in 0..3 {
+                self.level_sums[channel] += levels[channel] as u32;
+            }
+            self.level_count += 1;
+
+            // Each sample is the average output since the previous one
+            self.sample_rem += self.sample_rate;
+            if self.sample_rem >= self.cpu_hz {
+                self.sample_rem -= self.cpu_hz;
+                let count = self.level_count;
+                let sums = self.level_sums;
+                self.samples.push([(sums[0] / count) as u16,
+                                   (sums[1] / count) as u16,
+                                   (sums[2] / count) as u16]);
+                self.level_sums = [0; 3];
+                self.level_count = 0;
+            }
+        }
+    }
+
+    // Run up to the end of the frame, T states start again from 0. Output a
+    // write past the end already produced counts towards the next frame.
+    pub fn end_frame(&mut self, frame_tcycles: u32) {
+        self.catch_up(frame_tcycles);
+        self.tcycle = self.tcycle.saturating_sub(frame_tcycles);
+        if let Some(ref mut log) = self.log {
+            log.end_frame();
+        }
+    }
+
+    // Keep every register write from now on
+    pub fn start_log(&mut self) {
+        self.log = Some(AyLog::new(self.ay_hz));
+    }
+
+    pub fn take_log(&mut self) -> Option<AyLog> {
+        self.log.take()
+    }
+
+    // Output of channels A, B and C since the last call, one entry per sample
+    pub fn take_samples(&mut self) -> Vec<[u16; 3]> {
+        ::std::mem::replace(&mut self.samples, Vec::new())
+    }
+
+    fn tone_period(&self, channel: usize) -> u16 {
+        let period = self.registers[channel * 2] as u16 |
+                     (self.registers[channel * 2 + 1] as u16) << 8;
+        if period == 0 { 1 } else { period }
+    }
+
+    fn noise_period(&self) -> u16 {
+        let period = self.registers[6] as u16;
+        if period == 0 { 1 } else { period }
+    }
+
+    fn envelope_period(&self) -> u32 {
+        let period = self.registers[11] as u32 | (self.registers[12] as u32) << 8;
+        if period == 0 { 1 } else { period }
+    }
+
+    // Tone outputs flip every period steps, so a square wave lasts 16 AY
+    // clocks per unit of the period. Noise and envelope move at half that
+    // rate.
+    fn step(&mut self) {
+        for channel in 0..3 {
+            self.tone_counters[channel] += 1;
+            if self.tone_counters[channel] >= self.tone_period(channel) {
+                self.tone_counters[channel] = 0;
+                self.tone_outputs[channel] = !self.tone_outputs[channel];
+            }
+        }
+
+        self.noise_counter += 1;
+        if self.noise_counter >= 2 * self.noise_period() {
+            self.noise_counter = 0;
+            // 17 bit LFSR with taps at bits 0 and 3
+            let bit = (self.noise_lfsr ^ (self.noise_lfsr >> 3)) & 1;
+            self.noise_lfsr = (self.noise_lfsr >> 1) | (bit << 16);
+        }
+
+        self.envelope_counter += 1;
+        if self.envelope_counter >= 2 * self.envelope_period() {
+            self.envelope_counter = 0;
+            self.step_envelope();
+        }
+    }
+
+    fn step_envelope(&mut self) {
+        if self.envelope_holding {
+            return;
+        }
+        if self.envelope_step < 15 {
+            self.envelope_step += 1;
+            return;
+        }
+
+        let shape = self.registers[ENVELOPE_SHAPE];
+        if shape & CONTINUE == 0 {
+            self.envelope_holding = true;
+        } else if shape & HOLD != 0 {
+            if shape & ALTERNATE != 0 {
+                self.envelope_attack = !self.envelope_attack;
+            }
+            self.envelope_holding = true;
+        } else {
+            if shape & ALTERNATE != 0 {
+                self.envelope_attack = !self.envelope_attack;
+            }
+            self.envelope_step = 0;
+        }
+    }
+
+    fn envelope_level(&self) -> u8 {
+        // Shapes without CONTINUE drop to 0 at the end of the first cycle
+        if self.envelope_holding && self.registers[ENVELOPE_SHAPE] & CONTINUE == 0 {
+            return 0;
+        }
+        if self.envelope_attack {
+            self.envelope_step
+        } else {
+            15 - self.envelope_step
+        }
+    }
+
+    // A channel outputs its volume while both its enabled generators are
+    // high, channels with neither enabled are always on
+    fn levels(&self) -> [u16; 3] {
+        let mixer = self.registers[MIXER];
+        let noise = self.noise_lfsr & 1 != 0;
+        let mut levels = [0; 3];
+        for channel in 0..3 {
+            let tone_off = mixer & (0x01 << channel) != 0;
+            let noise_off = mixer & (0x08 << channel) != 0;
+            if (self.tone_outputs[channel] || tone_off) && (noise || noise_off) {
+                let volume = self.registers[8 + channel];
+                let volume = if volume & 0x10 != 0 { self.envelope_level() } else { volume };
+                levels[channel] = AY_LEVELS[volume as usize];
+            }
+        }
+        levels
     }
 }
 
 impl Peripheral for Ay {
-    fn read_port(&self, _: u16) -> u8 {
-        0xff
+    fn read_port(&mut self, _: u16) -> u8 {
+        self.registers[self.selected]
     }
 
-    fn write_port(&mut self, _: u16, val: u8) {
-        self.value = val;
+    fn write_port(&mut self, port: u16, val: u8) {
+        match port {
+            AY_SELECT_PORT => {
+                // Selecting anything past the 16 registers leaves them alone
+                if val < 16 {
+                    self.selected = val as usize;
+                }
+            }
+            AY_DATA_PORT => {
+                let selected = self.selected;
+                self.set_register(selected, val);
+                if let Some(ref mut log) = self.log {
+                    log.record(selected as u8, self.registers[selected]);
+                }
+            }
+            _ => {}
+        }
     }
 }
-
@@ -0,0 +1,146 @@
+use std::f32::consts::PI;
+
+// Width of the band-limited step in samples and the number of sub-sample
+// positions it is computed for
+const KERNEL_TAPS: usize = 16;
+const KERNEL_PHASES: usize = 32;
+
+lazy_static! {
+    // Windowed sinc impulse for each phase, cut off a little below the
+    // Nyquist frequency. Every edge adds one of these to the output, which
+    // integrates to a step without the aliasing of a sharp one.
+    static ref KERNEL: Vec<[f32; KERNEL_TAPS]> = {
+        let mut kernel = Vec::with_capacity(KERNEL_PHASES);
+        for phase in 0..KERNEL_PHASES {
+            let mut taps = [0.0; KERNEL_TAPS];
+            let centre = (KERNEL_TAPS / 2) as f32 + phase as f32 / KERNEL_PHASES as f32;
+            for k in 0..KERNEL_TAPS {
+                let x = k as f32 - centre;
+                let sinc = if x == 0.0 { 1.0 } else { (PI * 0.9 * x).sin() / (PI * 0.9 * x) };
+                // Blackman window, centred on the sinc
+                let w = 0.5 + x / (KERNEL_TAPS + 2) as f32;
+                let window = 0.42 - 0.5 * (2.0 * PI * w).cos() + 0.08 * (4.0 * PI * w).cos();
+                taps[k] = sinc * window;
+            }
+            let sum: f32 = taps.iter().sum();
+            for tap in taps.iter_mut() {
+                *tap /= sum;
+            }
+            kernel.push(taps);
+        }
+        kernel
+    };
+}
+
+// Output of an issue 3 machine for each combination of EAR (bit 4) and MIC
+// (bit 3), scaled from the measured voltages
+fn output_level(val: u8) -> f32 {
+    match (val & 0x10 != 0, val & 0x08 != 0) {
+        (false, false) => 0.0,
+        (false, true)  => 0.1,
+        (true, false)  => 0.96,
+        (true, true)   => 1.0,
+    }
+}
+
+// The speaker, driven by the EAR and MIC bits of port 0xFE. Level changes
+// are kept with their T state until the end of the frame, when they are
+// turned into samples.
+#[derive(RustcEncodable, RustcDecodable)]
+pub struct Beeper {
+    cpu_hz: u32,
+    sample_rate: u32,
+
+    last: u8,
+    // Changes of port 0xFE bits 3 and 4 in the current frame
+    edges: Vec<(u32, u8)>,
+
+    level: f32,
+    // Position of the start of the frame past the last sample, in CPU clock
+    // units
+    sample_rem: u64,
+    // Sum of the kernels of the edges so far, for this sample on
+    pending: Vec<f32>,
+    output: f32,
+    samples: Vec<u16>,
+}
+
+impl Beeper {
+    pub fn new() -> Self {
+        Beeper {
+            cpu_hz: 3_546_900,
+            sample_rate: 44100,
+            last: 0,
+            edges: Vec::new(),
+            level: 0.0,
+            sample_rem: 0,
+            pending: vec![0.0; KERNEL_TAPS],
+            output: 0.0,
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn set_clock(&mut self, cpu_hz: u32) {
+        self.cpu_hz = cpu_hz;
+        self.sample_rem = 0;
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.sample_rem = 0;
+    }
+
+    // Port 0xFE written at tcycle
+    pub fn write(&mut self, val: u8, tcycle: u32) {
+        let val = val & 0x18;
+        if val != self.last {
+            self.edges.push((tcycle, val));
+            self.last = val;
+        }
+    }
+
+    pub fn edges(&self) -> &[(u32, u8)] {
+        &self.edges
+    }
+
+    pub fn end_frame(&mut self, frame_tcycles: u32) {
+        let rate = self.sample_rate as u64;
+        let cpu_hz = self.cpu_hz as u64;
+
+        for &(tcycle, val) in &self.edges {
+            let position = self.sample_rem + tcycle as u64 * rate;
+            let sample = (position / cpu_hz) as usize;
+            let phase = ((position % cpu_hz) * KERNEL_PHASES as u64 / cpu_hz) as usize;
+
+            let level = output_level(val);
+            let delta = level - self.level;
+            self.level = level;
+
+            if self.pending.len() < sample + KERNEL_TAPS {
+                self.pending.resize(sample + KERNEL_TAPS, 0.0);
+            }
+            for (k, tap) in KERNEL[phase].iter().enumerate() {
+                self.pending[sample + k] += delta * tap;
+            }
+        }
+        self.edges.clear();
+
+        let end = self.sample_rem + frame_tcycles as u64 * rate;
+        let count = (end / cpu_hz) as usize;
+        self.sample_rem = end % cpu_hz;
+
+        if self.pending.len() < count + KERNEL_TAPS {
+            self.pending.resize(count + KERNEL_TAPS, 0.0);
+        }
+        for delta in self.pending.drain(..count) {
+            self.output += delta;
+            let sample = self.output.max(0.0).min(1.0);
+            self.samples.push((sample * 65535.0) as u16);
+        }
+    }
+
+    // Output since the last call, at the same scale as an AY channel
+    pub fn take_samples(&mut self) -> Vec<u16> {
+        ::std::mem::replace(&mut self.samples, Vec::new())
+    }
+}
@@ -0,0 +1,196 @@
+use super::Peripheral;
+
+const CONTROL_WORD: u8           = 0b00000001;
+const CONTROL_RESET: u8          = 0b00000010;
+const CONTROL_TIME_CONSTANT: u8  = 0b00000100;
+const CONTROL_TRIGGER_START: u8  = 0b00001000;
+const CONTROL_PRESCALER_256: u8  = 0b00100000;
+const CONTROL_COUNTER_MODE: u8   = 0b01000000;
+const CONTROL_INTERRUPT: u8      = 0b10000000;
+
+#[derive(RustcEncodable, RustcDecodable)]
+struct CtcChannel {
+    control: u8,
+    time_constant: u16,
+    counter: u16,
+    prescaler: u32,
+
+    running: bool,
+    waiting_for_time_constant: bool,
+    waiting_for_trigger: bool,
+
+    zero_counts: u32,
+    int_pending: bool,
+    int_in_service: bool,
+}
+
+impl CtcChannel {
+    fn new() -> Self {
+        CtcChannel {
+            control: CONTROL_RESET,
+            time_constant: 256,
+            counter: 256,
+            prescaler: 0,
+            running: false,
+            waiting_for_time_constant: false,
+            waiting_for_trigger: false,
+            zero_counts: 0,
+            int_pending: false,
+            int_in_service: false,
+        }
+    }
+
+    fn write(&mut self, val: u8) {
+        if self.waiting_for_time_constant {
+            self.waiting_for_time_constant = false;
+            self.time_constant = if val == 0 { 256 } else { val as u16 };
+            self.counter = self.time_constant;
+            self.prescaler = 0;
+
+            // A timer waiting for a trigger only starts on the next edge
+            self.waiting_for_trigger = self.control & CONTROL_COUNTER_MODE == 0 &&
+                                       self.control & CONTROL_TRIGGER_START != 0;
+            self.running = !self.waiting_for_trigger;
+            return;
+        }
+
+        self.control = val;
+        if val & CONTROL_INTERRUPT == 0 {
+            self.int_pending = false;
+        }
+        if val & CONTROL_RESET != 0 {
+            self.running = false;
+            self.int_pending = false;
+        }
+        self.waiting_for_time_constant = val & CONTROL_TIME_CONSTANT != 0;
+    }
+
+    fn prescaler_period(&self) -> u32 {
+        if self.control & CONTROL_PRESCALER_256 != 0 { 256 } else { 16 }
+    }
+
+    fn count(&mut self) {
+        self.counter -= 1;
+        if self.counter == 0 {
+            self.counter = self.time_constant;
+            self.zero_counts += 1;
+            if self.control & CONTROL_INTERRUPT != 0 {
+                self.int_pending = true;
+            }
+        }
+    }
+
+    fn clock(&mut self, tcycles: u32) {
+        if !self.running || self.control & CONTROL_COUNTER_MODE != 0 { return; }
+
+        self.prescaler += tcycles;
+        let period = self.prescaler_period();
+        while self.prescaler >= period {
+            self.prescaler -= period;
+            self.count();
+        }
+    }
+
+    fn trigger(&mut self) {
+        if self.waiting_for_time_constant { return; }
+
+        if self.waiting_for_trigger {
+            self.waiting_for_trigger = false;
+            self.running = true;
+        } else if self.running && self.control & CONTROL_COUNTER_MODE != 0 {
+            self.count();
+        }
+    }
+}
+
+// Z80 CTC: four 8-bit down counters, each clocked from the system clock
+// through a prescaler (timer mode) or by edges on its CLK/TRG input
+// (counter mode). Ports are decoded on A0-A1 for the channel number.
+// Channel 0 has the highest interrupt priority.
+#[derive(RustcEncodable, RustcDecodable)]
+pub struct Ctc {
+    channels: [CtcChannel; 4],
+    vector: u8,
+}
+
+impl Ctc {
+    pub fn new() -> Self {
+        Ctc {
+            channels: [CtcChannel::new(), CtcChannel::new(),
+                       CtcChannel::new(), CtcChannel::new()],
+            vector: 0,
+        }
+    }
+
+    // An active edge on the CLK/TRG input of a channel
+    pub fn trigger(&mut self, channel: usize) {
+        self.channels[channel].trigger();
+    }
+
+    // Number of ZC/TO pulses the channel produced since the last call, for
+    // cascading into other channels or clocking a SIO
+    pub fn take_zero_counts(&mut self, channel: usize) -> u32 {
+        let zero_counts = self.channels[channel].zero_counts;
+        self.channels[channel].zero_counts = 0;
+        zero_counts
+    }
+
+    // Highest priority channel that may interrupt: one under service masks
+    // itself and every channel below it
+    fn interrupting_channel(&self) -> Option<usize> {
+        for (i, channel) in self.channels.iter().enumerate() {
+            if channel.int_in_service { return None; }
+            if channel.int_pending { return Some(i); }
+        }
+        None
+    }
+}
+
+impl Peripheral for Ctc {
+    fn read_port(&mut self, port: u16) -> u8 {
+        self.channels[(port & 0x03) as usize].counter as u8
+    }
+
+    fn write_port(&mut self, port: u16, val: u8) {
+        let channel = (port & 0x03) as usize;
+        if val & CONTROL_WORD == 0 && !self.channels[channel].waiting_for_time_constant {
+            // Only channel 0 takes the vector; bits 1-2 identify the channel
+            if channel == 0 {
+                self.vector = val & 0xF8;
+            }
+        } else {
+            self.channels[channel].write(val);
+        }
+    }
+
+    fn clock(&mut self, tcycles: u32) {
+        for channel in self.channels.iter_mut() {
+            channel.clock(tcycles);
+        }
+    }
+
+    fn interrupt_pending(&mut self) -> bool {
+        self.interrupting_channel().is_some()
+    }
+
+    fn interrupt_in_service(&self) -> bool {
+        self.channels.iter().any(|channel| channel.int_in_service)
+    }
+
+    fn interrupt_acknowledge(&mut self) -> u8 {
+        match self.interrupting_channel() {
+            Some(i) => {
+                self.channels[i].int_pending = false;
+                self.channels[i].int_in_service = true;
+                self.vector | ((i as u8) << 1)
+            }
+            None => 0xFF,
+        }
+    }
+
+    fn reti(&mut self) {
+        if let Some(channel) = self.channels.iter_mut().find(|channel| channel.int_in_service) {
+            channel.int_in_service = false;
+        }
+    }
+}
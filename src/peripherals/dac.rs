@@ -0,0 +1,111 @@
+use super::Peripheral;
+
+// Both are decoded on the low byte of the port only
+pub const SPECDRUM_PORT: u16 = 0xdf;
+pub const COVOX_PORT: u16 = 0xfb;
+pub const DAC_PORT_MASK: u16 = 0x00ff;
+
+// An 8 bit DAC on a write-only port, like the Cheetah Specdrum or a Covox.
+// The port doesn't see the T state of the write, so the device counts them
+// itself from what the CPU reports, and every write takes the time of the
+// start of the instruction that made it. Values are held until the end of
+// the frame, when they are turned into samples.
+#[derive(RustcEncodable, RustcDecodable)]
+pub struct Dac {
+    cpu_hz: u32,
+    sample_rate: u32,
+
+    // T state of the current frame
+    tcycle: u32,
+    value: u8,
+    // Writes in the current frame
+    writes: Vec<(u32, u8)>,
+
+    // Fraction of the next sample, in CPU clock units
+    sample_rem: u32,
+    level_sum: u64,
+    level_count: u32,
+    samples: Vec<u16>,
+}
+
+impl Dac {
+    pub fn new() -> Self {
+        Dac {
+            cpu_hz: 3_546_900,
+            sample_rate: 44100,
+            tcycle: 0,
+            // Silence is half way, the samples are unsigned
+            value: 0x80,
+            writes: Vec::new(),
+            sample_rem: 0,
+            level_sum: 0,
+            level_count: 0,
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn set_clock(&mut self, cpu_hz: u32) {
+        self.cpu_hz = cpu_hz;
+        self.sample_rem = 0;
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.sample_rem = 0;
+    }
+
+    // Value written at tcycle
+    pub fn write(&mut self, val: u8, tcycle: u32) {
+        self.writes.push((tcycle, val));
+    }
+
+    pub fn writes(&self) -> &[(u32, u8)] {
+        &self.writes
+    }
+
+    // Each sample is the average of the values held since the previous one.
+    // T states past the end of the frame carry over to the next one.
+    pub fn end_frame(&mut self, frame_tcycles: u32) {
+        let mut writes = self.writes.drain(..).peekable();
+        for tcycle in 0..frame_tcycles {
+            while writes.peek().map_or(false, |&(at, _)| at <= tcycle) {
+                self.value = writes.next().unwrap().1;
+            }
+            self.level_sum += self.value as u64 * 0x101;
+            self.level_count += 1;
+
+            self.sample_rem += self.sample_rate;
+            if self.sample_rem >= self.cpu_hz {
+                self.sample_rem -= self.cpu_hz;
+                self.samples.push((self.level_sum / self.level_count as u64) as u16);
+                self.level_sum = 0;
+                self.level_count = 0;
+            }
+        }
+        if let Some((_, val)) = writes.last() {
+            self.value = val;
+        }
+        self.tcycle = self.tcycle.saturating_sub(frame_tcycles);
+    }
+
+    // Output since the last call, at the same scale as an AY channel
+    pub fn take_samples(&mut self) -> Vec<u16> {
+        ::std::mem::replace(&mut self.samples, Vec::new())
+    }
+}
+
+impl Peripheral for Dac {
+    // Nothing drives the data bus
+    fn read_port(&mut self, _: u16) -> u8 {
+        0xFF
+    }
+
+    fn write_port(&mut self, _: u16, val: u8) {
+        let tcycle = self.tcycle;
+        self.write(val, tcycle);
+    }
+
+    fn clock(&mut self, tcycles: u32) {
+        self.tcycle += tcycles;
+    }
+}
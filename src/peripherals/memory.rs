@@ -13,10 +13,23 @@ pub struct Memory {
     bank: [Box<[u8]>; 8],
 
     writable_rom: bool,
+    // 48K memory map: 0x7ffd is not decoded
+    paging_locked: bool,
+
+    // Address ranges with nothing behind them, as inclusive (start, end)
+    unmapped: Vec<(u16, u16)>,
 }
 
 impl Memory {
+    fn is_unmapped(&self, addr: u16) -> bool {
+        self.unmapped.iter().any(|&(start, end)| addr >= start && addr <= end)
+    }
+
     pub fn read_word(&self, addr: u16) -> u8 {
+        if self.is_unmapped(addr) {
+            return 0xFF;
+        }
+
         match addr {
             0x0000...0x3FFF => {
                 match self.rom {
@@ -33,6 +46,10 @@ impl Memory {
     }
 
     pub fn write_word(&mut self, addr: u16, val: u8) {
+        if self.is_unmapped(addr) {
+            return;
+        }
+
         if addr >= 0xC000 {
             self.bank[self.ram_0xc000_0xffff][(addr - 0xC000) as usize] = val;
         } else if addr >= 0x8000 && addr <= 0xBFFF {
@@ -93,11 +110,14 @@ impl Memory {
 }
 
 impl Peripheral for Memory {
-    fn read_port(&self, _: u16) -> u8 {
+    fn read_port(&mut self, _: u16) -> u8 {
         0
     }
 
     fn write_port(&mut self, _: u16, val: u8) {
+        if self.paging_locked {
+            return;
+        }
         self.change_bank(val & 0b00000111);
         self.change_rom_bank((val & 0b00010000) >> 4);
     }
@@ -128,6 +148,10 @@ pub struct MemoryBuilder {
     bank: [Box<[u8]>; 8],
 
     writable_rom: bool,
+
+    ram_size: Option<usize>,
+
+    unmapped: Vec<(u16, u16)>,
 }
 
 impl MemoryBuilder {
@@ -151,6 +175,10 @@ impl MemoryBuilder {
                    vec![0; 16 * 1024].into_boxed_slice()],
 
             writable_rom: false,
+
+            ram_size: None,
+
+            unmapped: Vec::new(),
         }
     }
 
@@ -184,7 +212,32 @@ impl MemoryBuilder {
         self
     }
 
-    pub fn finalize(self) -> Memory {
+    // Reads from [start, end] return 0xFF and writes are ignored
+    pub fn unmapped(mut self, start: u16, end: u16) -> MemoryBuilder {
+        self.unmapped.push((start, end));
+        self
+    }
+
+    // 48K memory map with only the first kb KB of RAM populated. The banks
+    // a 48K machine never sees are not allocated.
+    pub fn ram_size(mut self, kb: usize) -> MemoryBuilder {
+        assert!(kb >= 16 && kb <= 48, "RAM size must be between 16K and 48K");
+        self.ram_size = Some(kb);
+        self
+    }
+
+    pub fn finalize(mut self) -> Memory {
+        if let Some(kb) = self.ram_size {
+            for (i, bank) in self.bank.iter_mut().enumerate() {
+                if i != 0 && i != 2 && i != 5 {
+                    *bank = Vec::new().into_boxed_slice();
+                }
+            }
+            if kb < 48 {
+                self.unmapped.push(((0x4000 + kb * 1024) as u16, 0xFFFF));
+            }
+        }
+
         Memory {
             rom: self.rom,
             ram_0x4000_0x7fff: self.ram_0x4000_0x7fff,
@@ -196,6 +249,9 @@ impl MemoryBuilder {
             bank: self.bank,
 
             writable_rom: self.writable_rom,
+            paging_locked: self.ram_size.is_some(),
+
+            unmapped: self.unmapped,
         }
     }
 }
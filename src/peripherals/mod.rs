@@ -1,12 +1,50 @@
 mod memory;
 mod ay;
+mod beeper;
+mod dac;
 mod ula;
+mod ulaplus;
+mod timing;
+mod acia;
+mod ctc;
+mod pio;
+mod sio;
 
 pub use peripherals::memory::*;
 pub use peripherals::ula::*;
+pub use peripherals::ulaplus::*;
+pub use peripherals::timing::*;
 pub use peripherals::ay::*;
+pub use peripherals::beeper::*;
+pub use peripherals::dac::*;
+pub use peripherals::acia::*;
+pub use peripherals::ctc::*;
+pub use peripherals::pio::*;
+pub use peripherals::sio::*;
 
 pub trait Peripheral {
-    fn read_port(&self, port: u16) -> u8;
+    fn read_port(&mut self, port: u16) -> u8;
     fn write_port(&mut self, port: u16, val: u8);
+
+    // Advance the device by the number of T states the CPU just spent
+    fn clock(&mut self, _tcycles: u32) {}
+
+    // State of the device's /INT output, sampled before every instruction
+    fn interrupt_pending(&mut self) -> bool {
+        false
+    }
+
+    // The rest only matters to Z80 family devices in an IM 2 daisy chain.
+    // A device with an interrupt under service holds IEO low, which masks
+    // every device further down the chain until it sees RETI.
+    fn interrupt_in_service(&self) -> bool {
+        false
+    }
+
+    // Interrupt acknowledge cycle, returns the byte put on the data bus
+    fn interrupt_acknowledge(&mut self) -> u8 {
+        0xFF
+    }
+
+    fn reti(&mut self) {}
 }
@@ -0,0 +1,215 @@
+use super::Peripheral;
+
+use num::FromPrimitive;
+
+enum_from_primitive! {
+#[derive(Debug, Clone, Copy, PartialEq, RustcEncodable, RustcDecodable)]
+    pub enum PioMode {
+        Output        = 0,
+        Input         = 1,
+        Bidirectional = 2,
+        Control       = 3,
+    }
+}
+
+const INT_CONTROL_ENABLE: u8    = 0b10000000;
+const INT_CONTROL_AND: u8       = 0b01000000;
+const INT_CONTROL_HIGH: u8      = 0b00100000;
+const INT_CONTROL_MASK_NEXT: u8 = 0b00010000;
+
+#[derive(RustcEncodable, RustcDecodable)]
+struct PioPort {
+    mode: PioMode,
+    output: u8,
+    input: u8,
+
+    // Mode 3: 1 bits are inputs
+    direction: u8,
+
+    vector: u8,
+    int_control: u8,
+    // Mode 3: 0 bits are monitored by the interrupt logic
+    int_mask: u8,
+    int_enabled: bool,
+
+    expect_direction: bool,
+    expect_mask: bool,
+
+    // Mode 3 only interrupts when the monitored condition becomes true
+    condition: bool,
+
+    int_pending: bool,
+    int_in_service: bool,
+}
+
+impl PioPort {
+    fn new() -> Self {
+        PioPort {
+            mode: PioMode::Input,
+            output: 0,
+            input: 0xFF,
+            direction: 0xFF,
+            vector: 0,
+            int_control: 0,
+            int_mask: 0xFF,
+            int_enabled: false,
+            expect_direction: false,
+            expect_mask: false,
+            condition: false,
+            int_pending: false,
+            int_in_service: false,
+        }
+    }
+
+    fn write_control(&mut self, val: u8) {
+        if self.expect_direction {
+            self.expect_direction = false;
+            self.direction = val;
+            self.update_condition();
+            return;
+        }
+        if self.expect_mask {
+            self.expect_mask = false;
+            self.int_mask = val;
+            self.update_condition();
+            return;
+        }
+
+        match val & 0x0F {
+            0x0F => {
+                self.mode = PioMode::from_u8(val >> 6).unwrap();
+                self.expect_direction = self.mode == PioMode::Control;
+            }
+            0x07 => {
+                self.int_control = val;
+                self.int_enabled = val & INT_CONTROL_ENABLE != 0;
+                self.expect_mask = val & INT_CONTROL_MASK_NEXT != 0;
+                // A new control word clears any pending interrupt
+                self.int_pending = false;
+                self.update_condition();
+            }
+            0x03 => {
+                self.int_enabled = val & INT_CONTROL_ENABLE != 0;
+            }
+            _ if val & 0x01 == 0 => {
+                self.vector = val;
+            }
+            _ => {}
+        }
+    }
+
+    fn read_data(&self) -> u8 {
+        match self.mode {
+            PioMode::Output => self.output,
+            PioMode::Input | PioMode::Bidirectional => self.input,
+            PioMode::Control => (self.input & self.direction) | (self.output & !self.direction),
+        }
+    }
+
+    fn update_condition(&mut self) {
+        if self.mode != PioMode::Control { return; }
+
+        let monitored = !self.int_mask & self.direction;
+        let active = if self.int_control & INT_CONTROL_HIGH != 0 { self.input } else { !self.input };
+        let condition = if self.int_control & INT_CONTROL_AND != 0 {
+            monitored != 0 && active & monitored == monitored
+        } else {
+            active & monitored != 0
+        };
+
+        if condition && !self.condition && self.int_enabled {
+            self.int_pending = true;
+        }
+        self.condition = condition;
+    }
+}
+
+// Z80 PIO: two 8-bit ports. Port B/A select is decoded on A0 and
+// control/data on A1, which gives the data registers at base+0/1 and the
+// control registers at base+2/3. Port A has the higher interrupt priority.
+#[derive(RustcEncodable, RustcDecodable)]
+pub struct Pio {
+    ports: [PioPort; 2],
+}
+
+impl Pio {
+    pub fn new() -> Self {
+        Pio {
+            ports: [PioPort::new(), PioPort::new()],
+        }
+    }
+
+    // Level of the port pins as driven by the device the port is wired to
+    pub fn set_input(&mut self, port: usize, val: u8) {
+        self.ports[port].input = val;
+        self.ports[port].update_condition();
+    }
+
+    // Strobe a byte into a port in input mode, which requests an interrupt
+    pub fn strobe(&mut self, port: usize, val: u8) {
+        let port = &mut self.ports[port];
+        port.input = val;
+        if port.mode != PioMode::Control && port.int_enabled {
+            port.int_pending = true;
+        }
+    }
+
+    // Level of the port pins as driven by the PIO
+    pub fn output(&self, port: usize) -> u8 {
+        self.ports[port].output
+    }
+
+    fn interrupting_port(&self) -> Option<usize> {
+        for (i, port) in self.ports.iter().enumerate() {
+            if port.int_in_service { return None; }
+            if port.int_pending { return Some(i); }
+        }
+        None
+    }
+}
+
+impl Peripheral for Pio {
+    fn read_port(&mut self, port: u16) -> u8 {
+        let selected = (port & 0x01) as usize;
+        if port & 0x02 == 0 {
+            self.ports[selected].read_data()
+        } else {
+            // Control registers are write only
+            0xFF
+        }
+    }
+
+    fn write_port(&mut self, port: u16, val: u8) {
+        let selected = &mut self.ports[(port & 0x01) as usize];
+        if port & 0x02 == 0 {
+            selected.output = val;
+        } else {
+            selected.write_control(val);
+        }
+    }
+
+    fn interrupt_pending(&mut self) -> bool {
+        self.interrupting_port().is_some()
+    }
+
+    fn interrupt_in_service(&self) -> bool {
+        self.ports.iter().any(|port| port.int_in_service)
+    }
+
+    fn interrupt_acknowledge(&mut self) -> u8 {
+        match self.interrupting_port() {
+            Some(i) => {
+                self.ports[i].int_pending = false;
+                self.ports[i].int_in_service = true;
+                self.ports[i].vector
+            }
+            None => 0xFF,
+        }
+    }
+
+    fn reti(&mut self) {
+        if let Some(port) = self.ports.iter_mut().find(|port| port.int_in_service) {
+            port.int_in_service = false;
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use super::Peripheral;
+
+use std::io;
+use std::io::Write;
+use std::sync::mpsc::Receiver;
+
+const RR0_RX_CHAR_AVAILABLE: u8 = 0b00000001;
+const RR0_INT_PENDING: u8       = 0b00000010;
+const RR0_TX_BUFFER_EMPTY: u8   = 0b00000100;
+const RR0_DCD: u8               = 0b00001000;
+const RR0_CTS: u8               = 0b00100000;
+
+const RR1_ALL_SENT: u8          = 0b00000001;
+
+const WR1_TX_INT_ENABLE: u8     = 0b00000010;
+const WR1_STATUS_AFFECTS_VECTOR: u8 = 0b00000100;
+const WR1_RX_INT_MODE: u8       = 0b00011000;
+
+const WR3_RX_ENABLE: u8         = 0b00000001;
+const WR5_TX_ENABLE: u8         = 0b00001000;
+
+// Interrupt sources of a channel, in priority order
+const SOURCE_RX: usize = 0;
+const SOURCE_TX: usize = 1;
+
+struct SioChannel {
+    wr: [u8; 8],
+    pointer: usize,
+
+    rx_data: u8,
+    rx_available: bool,
+    // "Interrupt on first receive character" has been re-armed
+    rx_first_armed: bool,
+
+    int_pending: [bool; 2],
+    int_in_service: [bool; 2],
+
+    input: Option<Receiver<u8>>,
+    output: Box<dyn Write>,
+}
+
+impl SioChannel {
+    fn new() -> Self {
+        SioChannel {
+            wr: [0; 8],
+            pointer: 0,
+            rx_data: 0,
+            rx_available: false,
+            rx_first_armed: true,
+            int_pending: [false; 2],
+            int_in_service: [false; 2],
+            input: None,
+            output: Box::new(io::sink()),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.wr = [0; 8];
+        self.pointer = 0;
+        self.rx_available = false;
+        self.rx_first_armed = true;
+        self.int_pending = [false; 2];
+    }
+
+    fn rx_int_mode(&self) -> u8 {
+        (self.wr[1] & WR1_RX_INT_MODE) >> 3
+    }
+
+    fn receive(&mut self) {
+        if self.rx_available || self.wr[3] & WR3_RX_ENABLE == 0 { return; }
+
+        let byte = match self.input {
+            Some(ref input) => input.try_recv().ok(),
+            None => None,
+        };
+        if let Some(byte) = byte {
+            self.rx_data = byte;
+            self.rx_available = true;
+
+            let raise = match self.rx_int_mode() {
+                0 => false,
+                1 => {
+                    let armed = self.rx_first_armed;
+                    self.rx_first_armed = false;
+                    armed
+                }
+                _ => true,
+            };
+            if raise {
+                self.int_pending[SOURCE_RX] = true;
+            }
+        }
+    }
+
+    fn read_data(&mut self) -> u8 {
+        self.rx_available = false;
+        self.int_pending[SOURCE_RX] = false;
+        self.rx_data
+    }
+
+    fn write_data(&mut self, val: u8) {
+        if self.wr[5] & WR5_TX_ENABLE != 0 {
+            self.output.write_all(&[val]).unwrap();
+            self.output.flush().unwrap();
+        }
+        // Transmission is instantaneous, so the buffer is empty right away
+        self.int_pending[SOURCE_TX] = self.wr[1] & WR1_TX_INT_ENABLE != 0;
+    }
+
+    fn read_control(&mut self, int_pending: bool) -> u8 {
+        let reg = self.pointer;
+        self.pointer = 0;
+        match reg {
+            0 => {
+                let mut val = RR0_TX_BUFFER_EMPTY | RR0_DCD | RR0_CTS;
+                if self.rx_available { val |= RR0_RX_CHAR_AVAILABLE; }
+                if int_pending { val |= RR0_INT_PENDING; }
+                val
+            }
+            1 => RR1_ALL_SENT,
+            _ => 0,
+        }
+    }
+
+    // Returns true for a "return from interrupt" command
+    fn write_control(&mut self, val: u8) -> bool {
+        let reg = self.pointer;
+        self.pointer = 0;
+        if reg != 0 {
+            self.wr[reg] = val;
+            return false;
+        }
+
+        self.wr[0] = val;
+        self.pointer = (val & 0x07) as usize;
+        match (val >> 3) & 0x07 {
+            // Channel reset
+            3 => self.reset(),
+            // Enable interrupt on next receive character
+            4 => self.rx_first_armed = true,
+            // Reset transmitter interrupt pending
+            5 => self.int_pending[SOURCE_TX] = false,
+            // Return from interrupt
+            7 => return true,
+            _ => {}
+        }
+        false
+    }
+}
+
+// Z80 SIO/2 in asynchronous mode. A0 selects data (0) or control (1) and A1
+// channel A (0) or B (1). Interrupt priority runs channel A receive, channel
+// A transmit, channel B receive, channel B transmit. External/status
+// interrupts are not generated, the modem inputs always read as asserted.
+pub struct Sio {
+    channels: [SioChannel; 2],
+}
+
+impl Sio {
+    pub fn new() -> Self {
+        Sio {
+            channels: [SioChannel::new(), SioChannel::new()],
+        }
+    }
+
+    // Wire a channel's RxD and TxD lines to the host
+    pub fn connect(&mut self, channel: usize, input: Receiver<u8>, output: Box<dyn Write>) {
+        self.channels[channel].input = Some(input);
+        self.channels[channel].output = output;
+    }
+
+    fn vector(&self, channel: usize, source: usize) -> u8 {
+        let base = self.channels[1].wr[2];
+        if self.channels[1].wr[1] & WR1_STATUS_AFFECTS_VECTOR == 0 {
+            return base;
+        }
+
+        let status = match (channel, source) {
+            (1, SOURCE_TX) => 0b000,
+            (1, _) => 0b010,
+            (_, SOURCE_TX) => 0b100,
+            _ => 0b110,
+        };
+        (base & 0xF1) | (status << 1)
+    }
+
+    // Highest priority source that may interrupt, as (channel, source)
+    fn interrupting_source(&self) -> Option<(usize, usize)> {
+        for (c, channel) in self.channels.iter().enumerate() {
+            for source in 0..2 {
+                if channel.int_in_service[source] { return None; }
+                if channel.int_pending[source] { return Some((c, source)); }
+            }
+        }
+        None
+    }
+
+    fn end_of_interrupt(&mut self) {
+        for channel in self.channels.iter_mut() {
+            for source in 0..2 {
+                if channel.int_in_service[source] {
+                    channel.int_in_service[source] = false;
+                    return;
+                }
+            }
+        }
+    }
+}
+
+impl Peripheral for Sio {
+    fn read_port(&mut self, port: u16) -> u8 {
+        let int_pending = self.interrupting_source().is_some();
+        let channel = &mut self.channels[((port & 0x02) >> 1) as usize];
+        channel.receive();
+        if port & 0x01 == 0 {
+            channel.read_data()
+        } else {
+            // Only channel A reports the chip's pending interrupt
+            channel.read_control(int_pending && port & 0x02 == 0)
+        }
+    }
+
+    fn write_port(&mut self, port: u16, val: u8) {
+        let channel = &mut self.channels[((port & 0x02) >> 1) as usize];
+        if port & 0x01 == 0 {
+            channel.write_data(val);
+        } else if channel.write_control(val) && port & 0x02 == 0 {
+            self.end_of_interrupt();
+        }
+    }
+
+    fn interrupt_pending(&mut self) -> bool {
+        for channel in self.channels.iter_mut() {
+            channel.receive();
+        }
+        self.interrupting_source().is_some()
+    }
+
+    fn interrupt_in_service(&self) -> bool {
+        self.channels.iter().any(|channel| channel.int_in_service.iter().any(|&s| s))
+    }
+
+    fn interrupt_acknowledge(&mut self) -> u8 {
+        match self.interrupting_source() {
+            Some((c, source)) => {
+                self.channels[c].int_in_service[source] = true;
+                // A pending receive interrupt stays until the data is read
+                if source == SOURCE_TX {
+                    self.channels[c].int_pending[source] = false;
+                }
+                self.vector(c, source)
+            }
+            None => 0xFF,
+        }
+    }
+
+    fn reti(&mut self) {
+        self.end_of_interrupt();
+    }
+}
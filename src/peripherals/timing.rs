@@ -0,0 +1,123 @@
+// Frame and contention timings of the ULA. Contention starts one T state
+// before the ULA fetches the first byte of the screen and repeats on every
+// one of the 192 display lines for the 128 T states the line takes to draw.
+#[derive(Debug, Clone, Copy, PartialEq, RustcEncodable, RustcDecodable)]
+pub struct UlaTiming {
+    pub clock_hz: u32,
+    pub frame_tcycles: u32,
+    pub line_tcycles: u32,
+    pub first_contended: u32,
+    // T state at which the top left pixel of the paper is drawn
+    pub first_pixel: u32,
+
+    // Delay for each T state of an 8 T state fetch cycle
+    pub pattern: [u8; 8],
+
+    // Bit n set if RAM bank n is contended
+    pub contended_banks: u8,
+
+    // Even ports are contended through the ULA
+    pub contended_ports: bool,
+
+    // Unattached ports read whatever the ULA is fetching for the display
+    pub floating_bus: bool,
+}
+
+pub const SPECTRUM_48K: UlaTiming = UlaTiming {
+    clock_hz: 3_500_000,
+    frame_tcycles: 69888,
+    line_tcycles: 224,
+    first_contended: 14335,
+    first_pixel: 14336,
+    pattern: [6, 5, 4, 3, 2, 1, 0, 0],
+    contended_banks: 0b00100000,
+    contended_ports: true,
+    floating_bus: true,
+};
+
+pub const SPECTRUM_128K: UlaTiming = UlaTiming {
+    clock_hz: 3_546_900,
+    frame_tcycles: 70908,
+    line_tcycles: 228,
+    first_contended: 14361,
+    first_pixel: 14362,
+    pattern: [6, 5, 4, 3, 2, 1, 0, 0],
+    contended_banks: 0b10101010,
+    contended_ports: true,
+    floating_bus: true,
+};
+
+pub const SPECTRUM_PLUS2A: UlaTiming = UlaTiming {
+    clock_hz: 3_546_900,
+    frame_tcycles: 70908,
+    line_tcycles: 228,
+    first_contended: 14365,
+    first_pixel: 14366,
+    pattern: [1, 0, 7, 6, 5, 4, 3, 2],
+    contended_banks: 0b11110000,
+    contended_ports: false,
+    floating_bus: false,
+};
+
+// The Pentagon has no contention at all
+pub const PENTAGON: UlaTiming = UlaTiming {
+    clock_hz: 3_500_000,
+    frame_tcycles: 71680,
+    line_tcycles: 224,
+    first_contended: 0,
+    first_pixel: 17988,
+    pattern: [0; 8],
+    contended_banks: 0,
+    contended_ports: false,
+    floating_bus: false,
+};
+
+impl UlaTiming {
+    // Extra T states an access to contended memory starting at tcycle
+    // takes. Any T state is valid, frames that overrun wrap around.
+    pub fn delay(&self, tcycle: u32) -> u32 {
+        let tcycle = tcycle % self.frame_tcycles;
+        if tcycle < self.first_contended {
+            return 0;
+        }
+
+        let offset = tcycle - self.first_contended;
+        let line = offset / self.line_tcycles;
+        let column = offset % self.line_tcycles;
+        if line >= 192 || column >= 128 {
+            return 0;
+        }
+        self.pattern[(column % 8) as usize] as u32
+    }
+
+    // Address of the screen byte the ULA is reading at tcycle, if any. The
+    // first fetch comes 3 T states into the contended period, then every 8
+    // T states the ULA reads bitmap, attribute, bitmap, attribute for two
+    // character cells and leaves the bus idle for the other 4.
+    pub fn fetch_address(&self, tcycle: u32) -> Option<u16> {
+        let tcycle = tcycle % self.frame_tcycles;
+        let first_fetch = self.first_contended + 3;
+        if !self.floating_bus || tcycle < first_fetch {
+            return None;
+        }
+
+        let offset = tcycle - first_fetch;
+        let line = offset / self.line_tcycles;
+        let column = offset % self.line_tcycles;
+        if line >= 192 || column >= 128 {
+            return None;
+        }
+
+        let x = (column / 8) * 2 + (column % 8) / 2;
+        match column % 8 {
+            0 | 2 => Some((0x4000 | ((line & 0xC0) << 5) | ((line & 0x07) << 8) |
+                           ((line & 0x38) << 2) | x) as u16),
+            1 | 3 => Some((0x5800 + (line / 8) * 32 + x) as u16),
+            _ => None,
+        }
+    }
+
+    pub fn is_bank_contended(&self, bank: u8) -> bool {
+        self.contended_banks & (1 << bank) != 0
+    }
+}
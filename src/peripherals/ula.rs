@@ -1,13 +1,17 @@
 use super::Peripheral;
 use super::Memory;
+use super::timing::*;
+use super::ulaplus::*;
+use super::beeper::Beeper;
 use machine::SpectrumKeycode;
 
 use std::rc::Rc;
 use std::cell::RefCell;
 
-use sdl2::render::Texture;
+use framebuffer::Framebuffer;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
 
 
 lazy_static! {
@@ -83,51 +87,285 @@ static COLOURS: [(u8, u8, u8); 16] = [
     ( 255, 255, 255 )
 ];
 
+// Size of the visible border around the 256x192 paper
+pub const BORDER_LEFT: usize = 48;
+pub const BORDER_RIGHT: usize = 48;
+pub const BORDER_TOP: usize = 48;
+pub const BORDER_BOTTOM: usize = 56;
+
+pub const DISPLAY_WIDTH: usize = BORDER_LEFT + 256 + BORDER_RIGHT;
+pub const DISPLAY_HEIGHT: usize = BORDER_TOP + 192 + BORDER_BOTTOM;
+
+// With no tape signal, what port 0xFE bit 6 reads back depends on the
+// last values written to the EAR and MIC bits, through a level threshold
+// that moved between board issues
+#[derive(Debug, Clone, Copy, PartialEq, RustcEncodable, RustcDecodable)]
+pub enum Issue {
+    // Bit 6 is set when EAR or MIC was
+    Issue2,
+    // Bit 6 follows EAR alone, as on the 128K
+    Issue3,
+}
+
+impl FromStr for Issue {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "2" => Ok(Issue::Issue2),
+            "3" => Ok(Issue::Issue3),
+            _ => Err(format!("Unknown issue: {}", s)),
+        }
+    }
+}
+
 #[derive(RustcEncodable, RustcDecodable)]
 pub struct Ula {
+    // Last value written to port 0xFE
     value: u8,
+    issue: Issue,
+
+    // Level of the tape signal on the EAR socket, None with no tape
+    // playing, and changes of it still to come in this frame
+    ear_input: Option<bool>,
+    ear_edges: VecDeque<(u32, bool)>,
 
     memory: Rc<RefCell<Memory>>,
 
     keyboard_ports: [u8; 8],
+
+    timing: UlaTiming,
+
+    border: u8,
+
+    // Colour index of every pixel of the display, drawn as the beam gets
+    // to it, and the T state the beam has reached
+    pixels: Vec<u8>,
+    beam: u32,
+
+    // FLASH cells swap ink and paper every 16 frames
+    frame_count: u32,
+
+    ulaplus: Option<UlaPlus>,
+
+    beeper: Beeper,
 }
 
 impl Ula {
     pub fn new(memory: Rc<RefCell<Memory>>) -> Self {
         Ula { value: 0,
+              issue: Issue::Issue3,
+              ear_input: None,
+              ear_edges: VecDeque::new(),
               memory,
               keyboard_ports: [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+              timing: SPECTRUM_128K,
+              border: 7,
+              pixels: vec![7; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+              beam: 0,
+              frame_count: 0,
+              ulaplus: None,
+              beeper: Beeper::new(),
+        }
+    }
+
+    pub fn set_timing(&mut self, timing: UlaTiming) {
+        self.timing = timing;
+    }
+
+    pub fn set_issue(&mut self, issue: Issue) {
+        self.issue = issue;
+    }
+
+    pub fn get_issue(&self) -> Issue {
+        self.issue
+    }
+
+    // Drive the EAR input from now on, or let go of it
+    pub fn set_ear_input(&mut self, level: Option<bool>) {
+        self.ear_edges.clear();
+        self.ear_input = level;
+    }
+
+    pub fn get_ear_input(&self) -> Option<bool> {
+        self.ear_input
+    }
+
+    // Have the tape signal change to level at tcycle of this frame. Edges
+    // have to come in order.
+    pub fn add_ear_edge(&mut self, tcycle: u32, level: bool) {
+        self.ear_edges.push_back((tcycle, level));
+    }
+
+    fn catch_up_ear(&mut self, tcycle: u32) {
+        while self.ear_edges.front().map_or(false, |&(at, _)| at <= tcycle) {
+            self.ear_input = Some(self.ear_edges.pop_front().unwrap().1);
+        }
+    }
+
+    // Port reads carry the T state so that they see the tape signal as it
+    // is at the time
+    pub fn read_port_at(&mut self, port: u16, tcycle: u32) -> u8 {
+        self.catch_up_ear(tcycle);
+        self.read_port(port)
+    }
+
+    // Port 0xFE bit 6
+    fn ear_bit(&self) -> bool {
+        match (self.ear_input, self.issue) {
+            (Some(level), _) => level,
+            (None, Issue::Issue2) => self.value & 0x18 != 0,
+            (None, Issue::Issue3) => self.value & 0x10 != 0,
+        }
+    }
+
+    pub fn enable_ulaplus(&mut self) {
+        self.ulaplus = Some(UlaPlus::new());
+    }
+
+    pub fn has_ulaplus(&self) -> bool {
+        self.ulaplus.is_some()
+    }
+
+    pub fn ulaplus(&self) -> Option<&UlaPlus> {
+        self.ulaplus.as_ref()
+    }
+
+    // Bring back a saved palette and mode
+    pub fn set_ulaplus(&mut self, ulaplus: UlaPlus) {
+        self.ulaplus = Some(ulaplus);
+    }
+
+    pub fn write_ulaplus_port(&mut self, port: u16, val: u8, tcycle: u32) {
+        self.catch_up(tcycle);
+        if let Some(ref mut ulaplus) = self.ulaplus {
+            ulaplus.write_port(port, val);
+        }
+    }
+
+    pub fn read_ulaplus_port(&self, port: u16) -> u8 {
+        match self.ulaplus {
+            Some(ref ulaplus) => ulaplus.read_port(port),
+            None => 0xFF,
         }
     }
 
-    pub fn display(&self, texture: &mut Texture) {
-        texture.with_lock(None, |buffer: &mut [u8], pitch: usize| {
-            for addr in 0x4000..0x5800 {
-                let dispx: usize = addr & 0x001F;
-                let dispy: usize = ((addr & 0x0700) >> 8 ) |
-                                   ((addr & 0x00E0) >> 2 ) |
-                                   ((addr & 0x1800) >> 5 ) ;
+    fn palette_mode(&self) -> bool {
+        self.ulaplus.as_ref().map_or(false, |ulaplus| ulaplus.palette_mode())
+    }
+
+    pub fn get_border(&self) -> u8 {
+        self.border
+    }
+
+    // Colour indices of the display, one byte per pixel, row by row. In
+    // ULAplus palette mode they are 16 plus the palette entry.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn beeper_mut(&mut self) -> &mut Beeper {
+        &mut self.beeper
+    }
+
+    // Port writes carry the T state so that border changes are drawn where
+    // the beam was at the time, and the speaker moves when it should. The
+    // speaker counts CPU T states and the beam ULA ones, which only differ
+    // in turbo modes.
+    pub fn write_port_at(&mut self, port: u16, val: u8, tcycle: u32, beam_tcycle: u32) {
+        self.catch_up(beam_tcycle);
+        self.beeper.write(val, tcycle);
+        self.write_port(port, val);
+    }
+
+    // T state at which the beam is at the top left corner of the display
+    fn display_start(&self) -> u32 {
+        self.timing.first_pixel -
+            (BORDER_TOP as u32) * self.timing.line_tcycles -
+            (BORDER_LEFT as u32) / 2
+    }
+
+    // Draw everything the beam went over up to tcycle. Has to be called
+    // before anything that changes what is on screen.
+    pub fn catch_up(&mut self, tcycle: u32) {
+        let start = self.display_start();
+        let end = start + (DISPLAY_HEIGHT as u32) * self.timing.line_tcycles;
+        let tcycle = if tcycle > end { end } else { tcycle };
+
+        while self.beam < tcycle {
+            let t = self.beam;
+            self.beam += 1;
+            if t < start { continue; }
+
+            // Two pixels per T state
+            let y = ((t - start) / self.timing.line_tcycles) as usize;
+            let x = ((t - start) % self.timing.line_tcycles) as usize * 2;
+            if x >= DISPLAY_WIDTH { continue; }
+
+            self.draw(x, y);
+            self.draw(x + 1, y);
+        }
+    }
 
-                let pixels = self.memory.borrow().read_word(addr as u16);
+    fn draw(&mut self, x: usize, y: usize) {
+        let colour = if x >= BORDER_LEFT && x < BORDER_LEFT + 256 &&
+                        y >= BORDER_TOP && y < BORDER_TOP + 192 {
+            let dispx = x - BORDER_LEFT;
+            let dispy = y - BORDER_TOP;
+            let addr = 0x4000 | ((dispy & 0xC0) << 5) | ((dispy & 0x07) << 8) |
+                       ((dispy & 0x38) << 2) | (dispx / 8);
+            let pixels = self.memory.borrow().read_word(addr as u16);
+            let attrdata = self.memory.borrow().read_word((0x5800 + (dispy / 8) * 32 + dispx / 8) as u16);
 
-                let attrx = dispx;
-                let attry = dispy / 8;
-                let attr = attry * 32 + attrx;
-                let attrdata = self.memory.borrow().read_word((0x5800 + attr) as u16);
+            let mut set = pixels & (0x80 >> (dispx % 8)) != 0;
+            if self.palette_mode() {
+                16 + UlaPlus::attribute_entry(attrdata, set)
+            } else {
                 let ink = attrdata & 0x07;
                 let paper = (attrdata & 0x38) >> 3;
                 let brightness = if attrdata & 0x40 != 0 { 8 } else { 0 };
+                if attrdata & 0x80 != 0 && self.flash_inverted() {
+                    set = !set;
+                }
+                let colour = if set { ink } else { paper };
+                colour + brightness
+            }
+        } else if self.palette_mode() {
+            16 + UlaPlus::border_entry(self.border)
+        } else {
+            self.border
+        };
+        self.pixels[y * DISPLAY_WIDTH + x] = colour;
+    }
 
-                for p in 0..8 {
-                    let offset = dispy * pitch + ((dispx * 8) + (7 - p)) * 3;
-                    let colour = if pixels & (1 << p) != 0 { ink } else { paper };
+    fn flash_inverted(&self) -> bool {
+        (self.frame_count / 16) % 2 != 0
+    }
 
-                    buffer[offset + 0] = COLOURS[(colour + brightness) as usize].0;
-                    buffer[offset + 1] = COLOURS[(colour + brightness) as usize].1;
-                    buffer[offset + 2] = COLOURS[(colour + brightness) as usize].2;
-                }
+    pub fn get_frame_count(&self) -> u32 {
+        self.frame_count
+    }
+
+    // Finish drawing the frame, the beam goes back to the top
+    pub fn end_frame(&mut self) {
+        let frame_tcycles = self.timing.frame_tcycles;
+        self.catch_up(frame_tcycles);
+        self.beam = 0;
+        self.catch_up_ear(u32::max_value());
+        self.frame_count = self.frame_count.wrapping_add(1);
+    }
+
+    pub fn display(&self, framebuffer: &mut Framebuffer) {
+        for y in 0..DISPLAY_HEIGHT {
+            for x in 0..DISPLAY_WIDTH {
+                let colour = self.pixels[y * DISPLAY_WIDTH + x];
+                let rgb = match self.ulaplus {
+                    Some(ref ulaplus) if colour >= 16 => ulaplus.rgb(colour - 16),
+                    _ => COLOURS[colour as usize],
+                };
+                framebuffer.set_pixel(x, y, rgb);
             }
-        }).unwrap();
+        }
     }
 
     pub fn key_pressed(&mut self, keycode: &SpectrumKeycode) {
@@ -150,7 +388,7 @@ impl Ula {
 }
 
 impl Peripheral for Ula {
-    fn read_port(&self, port: u16) -> u8 {
+    fn read_port(&mut self, port: u16) -> u8 {
         let mut data = 0xff;
         let mut porth: u8 = (port >> 8) as u8;
         for i in 0..8 {
@@ -159,15 +397,12 @@ impl Peripheral for Ula {
             }
             porth = porth >> 1;
         }
-        data
+        if self.ear_bit() { data } else { data & !0x40 }
     }
 
     fn write_port(&mut self, _: u16, val: u8) {
-        if val & 0x10 != 0 {
-            self.value = 0xff;
-        } else {
-            self.value = 0xbf;
-        }
+        self.border = val & 0x07;
+        self.value = val;
     }
 }
 
@@ -0,0 +1,94 @@
+// ULAplus palette extension. A register port selects either one of the 64
+// palette entries or the mode register, the data port reads and writes it.
+// In palette mode the two top bits of an attribute pick one of four 16
+// colour tables (CLUTs), made of 8 inks followed by 8 papers.
+pub const ULAPLUS_REGISTER_PORT: u16 = 0xbf3b;
+pub const ULAPLUS_DATA_PORT: u16 = 0xff3b;
+
+#[derive(Clone, RustcEncodable, RustcDecodable)]
+pub struct UlaPlus {
+    // Colours as GRB332
+    palette: Vec<u8>,
+    register: u8,
+    mode: u8,
+}
+
+impl UlaPlus {
+    pub fn new() -> Self {
+        UlaPlus {
+            palette: vec![0; 64],
+            register: 0,
+            mode: 0,
+        }
+    }
+
+    pub fn palette_mode(&self) -> bool {
+        self.mode & 0x01 != 0
+    }
+
+    pub fn get_entry(&self, entry: u8) -> u8 {
+        self.palette[(entry & 0x3F) as usize]
+    }
+
+    pub fn set_entry(&mut self, entry: u8, val: u8) {
+        self.palette[(entry & 0x3F) as usize] = val;
+    }
+
+    // Register group in the top two bits: 0 is the palette, 1 the mode
+    fn group(&self) -> u8 {
+        self.register >> 6
+    }
+
+    pub fn write_port(&mut self, port: u16, val: u8) {
+        match port {
+            ULAPLUS_REGISTER_PORT => self.register = val,
+            ULAPLUS_DATA_PORT => match self.group() {
+                0 => {
+                    let entry = self.register;
+                    self.set_entry(entry, val);
+                }
+                1 => self.mode = val,
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    pub fn read_port(&self, _: u16) -> u8 {
+        match self.group() {
+            0 => self.get_entry(self.register),
+            1 => self.mode,
+            _ => 0xFF,
+        }
+    }
+
+    // Palette entry for a pixel with the given attribute. BRIGHT and FLASH
+    // become the CLUT number.
+    pub fn attribute_entry(attr: u8, set: bool) -> u8 {
+        let clut = (attr & 0xC0) >> 2;
+        if set {
+            clut | (attr & 0x07)
+        } else {
+            clut | 0x08 | ((attr >> 3) & 0x07)
+        }
+    }
+
+    // The border uses the papers of the first CLUT
+    pub fn border_entry(border: u8) -> u8 {
+        0x08 | (border & 0x07)
+    }
+
+    // Blue only has two bits, the missing low one is set if either of them is
+    pub fn rgb(&self, entry: u8) -> (u8, u8, u8) {
+        let colour = self.get_entry(entry);
+        let g = (colour >> 5) & 0x07;
+        let r = (colour >> 2) & 0x07;
+        let b = ((colour & 0x03) << 1) | if colour & 0x03 != 0 { 1 } else { 0 };
+        (scale(r), scale(g), scale(b))
+    }
+}
+
+// 3 bits to 8 bits
+fn scale(val: u8) -> u8 {
+    (val << 5) | (val << 2) | (val >> 1)
+}
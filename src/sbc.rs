@@ -0,0 +1,139 @@
+use ::interconnect::*;
+use ::peripherals::*;
+use ::cpu::*;
+use ::debugger::*;
+
+use std::rc::Rc;
+use std::cell::RefCell;
+
+// RC2014-style single-board computer: a ROM at the bottom of the address
+// space, RAM from ram_start up to 0xFFFF and an MC6850 ACIA serial console
+pub struct Sbc {
+    cpu: Rc<RefCell<Cpu>>,
+    memory: Rc<RefCell<Memory>>,
+    debug_on: bool,
+}
+
+impl Sbc {
+    pub fn cpu(&self) -> Rc<RefCell<Cpu>> {
+        self.cpu.clone()
+    }
+
+    pub fn memory(&self) -> Rc<RefCell<Memory>> {
+        self.memory.clone()
+    }
+
+    pub fn step(&self) {
+        self.cpu.borrow_mut().handle_interrupts();
+        self.cpu.borrow_mut().run_instruction();
+    }
+
+    pub fn run(&self) {
+        let mut debugger = Debugger::new(
+            self.cpu.clone(),
+            self.memory.clone());
+
+        loop {
+            if self.debug_on { debugger.pre(); }
+
+            self.step();
+
+            if self.debug_on { debugger.post(); }
+        }
+    }
+}
+
+pub struct SbcBuilder {
+    rom: Box<[u8]>,
+    rom_size: usize,
+    rom_page: usize,
+    ram_start: u16,
+
+    acia: Option<Acia>,
+    acia_port: u8,
+
+    debug_on: bool,
+}
+
+impl SbcBuilder {
+    pub fn new(rom: Box<[u8]>) -> Self {
+        SbcBuilder {
+            rom,
+            rom_size: 8 * 1024,
+            rom_page: 0,
+            ram_start: 0x8000,
+
+            acia: None,
+            acia_port: 0x80,
+
+            debug_on: false,
+        }
+    }
+
+    // Size of the ROM window at 0x0000, at most 16K
+    pub fn rom_size(mut self, size: usize) -> SbcBuilder {
+        self.rom_size = size;
+        self
+    }
+
+    // Which rom_size sized page of a multi-ROM image is selected by the
+    // address jumpers
+    pub fn rom_page(mut self, page: usize) -> SbcBuilder {
+        self.rom_page = page;
+        self
+    }
+
+    pub fn ram_start(mut self, addr: u16) -> SbcBuilder {
+        self.ram_start = addr;
+        self
+    }
+
+    // Defaults to an ACIA on the host terminal at ports 0x80/0x81
+    pub fn acia(mut self, acia: Acia, port: u8) -> SbcBuilder {
+        self.acia = Some(acia);
+        self.acia_port = port;
+        self
+    }
+
+    pub fn debug(mut self, val: bool) -> SbcBuilder {
+        self.debug_on = val;
+        self
+    }
+
+    pub fn finalize(self) -> Sbc {
+        assert!(self.rom_size > 0 && self.rom_size <= 0x4000,
+                "the ROM window must fit in the first 16K");
+        assert!(self.ram_start >= 0x4000,
+                "RAM can only start in the upper 48K");
+
+        let mut rom0 = vec![0xFF; 16 * 1024].into_boxed_slice();
+        let page_start = self.rom_page * self.rom_size;
+        if page_start < self.rom.len() {
+            let page_end = (page_start + self.rom_size).min(self.rom.len());
+            rom0[..page_end - page_start].copy_from_slice(&self.rom[page_start..page_end]);
+        }
+
+        let memory = Rc::new(RefCell::new(MemoryBuilder::new()
+            .rom0(rom0)
+            .unmapped(self.rom_size as u16, self.ram_start - 1)
+            .finalize()));
+
+        let acia = Rc::new(RefCell::new(match self.acia {
+            Some(acia) => acia,
+            None => Acia::with_stdio(),
+        }));
+
+        // The ACIA only decodes the low address byte, with A0 selecting
+        // between the control/status and data registers
+        let mut interconnect = Interconnect::without_peripherals(memory.clone());
+        interconnect.attach(acia, 0x00FE, self.acia_port as u16 & 0x00FE);
+
+        let cpu = Rc::new(RefCell::new(Cpu::new(interconnect)));
+
+        Sbc {
+            cpu,
+            memory,
+            debug_on: self.debug_on,
+        }
+    }
+}
@@ -0,0 +1,143 @@
+// Pulse lengths of the ROM's save routine, in T states at 3.5MHz
+pub const PILOT_PULSE: u32 = 2168;
+pub const HEADER_PILOT_PULSES: u32 = 8063;
+pub const DATA_PILOT_PULSES: u32 = 3223;
+pub const SYNC1_PULSE: u32 = 667;
+pub const SYNC2_PULSE: u32 = 735;
+pub const ZERO_PULSE: u32 = 855;
+pub const ONE_PULSE: u32 = 1710;
+
+// Tape timings are given for a 3.5MHz clock
+pub const TAPE_CLOCK_HZ: u32 = 3_500_000;
+pub const TSTATES_PER_MS: u32 = TAPE_CLOCK_HZ / 1000;
+
+// Bytes sent the way the ROM does it: a pilot tone, two sync pulses, then
+// two pulses per bit, most significant bit first. The first byte is the
+// flag the loader looks for and the last one the XOR of all the others.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataBlock {
+    pub pilot_pulse: u32,
+    pub pilot_pulses: u32,
+    pub sync1_pulse: u32,
+    pub sync2_pulse: u32,
+    pub zero_pulse: u32,
+    pub one_pulse: u32,
+    // Bits of the last byte that are sent, from the top
+    pub last_byte_bits: u8,
+    // Silence after the block
+    pub pause_ms: u32,
+    pub data: Vec<u8>,
+}
+
+impl DataBlock {
+    // A block saved by the ROM, headers (flag below 0x80) have a longer
+    // pilot tone
+    pub fn standard(data: Vec<u8>, pause_ms: u32) -> Self {
+        let header = data.first().map_or(false, |&flag| flag < 0x80);
+        DataBlock {
+            pilot_pulse: PILOT_PULSE,
+            pilot_pulses: if header { HEADER_PILOT_PULSES } else { DATA_PILOT_PULSES },
+            sync1_pulse: SYNC1_PULSE,
+            sync2_pulse: SYNC2_PULSE,
+            zero_pulse: ZERO_PULSE,
+            one_pulse: ONE_PULSE,
+            last_byte_bits: 8,
+            pause_ms,
+            data,
+        }
+    }
+
+    // Length of every pulse, each one flipping the signal
+    pub fn pulses(&self) -> Vec<u32> {
+        let mut pulses = vec![self.pilot_pulse; self.pilot_pulses as usize];
+        if self.sync1_pulse > 0 {
+            pulses.push(self.sync1_pulse);
+        }
+        if self.sync2_pulse > 0 {
+            pulses.push(self.sync2_pulse);
+        }
+        pulses.extend(data_pulses(&self.data, self.last_byte_bits, self.zero_pulse, self.one_pulse));
+        pulses
+    }
+}
+
+// Two pulses per bit, most significant bit first
+pub fn data_pulses(data: &[u8], last_byte_bits: u8, zero_pulse: u32, one_pulse: u32) -> Vec<u32> {
+    let mut pulses = Vec::with_capacity(data.len() * 16);
+    for (i, &byte) in data.iter().enumerate() {
+        let bits = if i + 1 == data.len() { last_byte_bits } else { 8 };
+        for bit in 0..bits {
+            let pulse = if byte & (0x80 >> bit) != 0 { one_pulse } else { zero_pulse };
+            pulses.push(pulse);
+            pulses.push(pulse);
+        }
+    }
+    pulses
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    Data(DataBlock),
+    // count pulses of the same length
+    PureTone { pulse: u32, count: u32 },
+    Pulses(Vec<u32>),
+    // The signal sampled every tstates_per_sample T states, one bit per
+    // sample, 1 for high
+    DirectRecording {
+        tstates_per_sample: u32,
+        pause_ms: u32,
+        last_byte_bits: u8,
+        data: Vec<u8>,
+    },
+    // Silence, a pause of 0 stops the tape
+    Pause(u32),
+    SetLevel(bool),
+    StopIf48K,
+    // Where playback goes next
+    GroupStart(String),
+    GroupEnd,
+    Jump(i16),
+    LoopStart(u16),
+    LoopEnd,
+    // Text and other blocks with nothing to play
+    Info(String),
+}
+
+impl Block {
+    // Short description for block lists
+    pub fn describe(&self) -> String {
+        match *self {
+            Block::Data(ref block) => describe_data(&block.data),
+            Block::PureTone { pulse, count } => format!("Pure tone, {} pulses of {} T states", count, pulse),
+            Block::Pulses(ref pulses) => format!("{} pulses", pulses.len()),
+            Block::DirectRecording { ref data, .. } => format!("Direct recording, {} bytes", data.len()),
+            Block::Pause(0) => "Stop the tape".to_string(),
+            Block::Pause(ms) => format!("Pause, {} ms", ms),
+            Block::SetLevel(level) => format!("Signal {}", if level { "high" } else { "low" }),
+            Block::StopIf48K => "Stop the tape on a 48K".to_string(),
+            Block::GroupStart(ref name) => format!("Group: {}", name),
+            Block::GroupEnd => "Group end".to_string(),
+            Block::Jump(offset) => format!("Jump by {} blocks", offset),
+            Block::LoopStart(count) => format!("Loop {} times", count),
+            Block::LoopEnd => "Loop end".to_string(),
+            Block::Info(ref text) => text.clone(),
+        }
+    }
+}
+
+// Headers carry the type and name of the file that follows
+fn describe_data(data: &[u8]) -> String {
+    if data.len() == 19 && data[0] == 0x00 {
+        let name: String = data[2..12].iter().map(|&c| c as char).collect();
+        let kind = match data[1] {
+            0 => "Program",
+            1 => "Number array",
+            2 => "Character array",
+            3 => "Bytes",
+            _ => "Header",
+        };
+        format!("{}: {}", kind, name.trim_end())
+    } else {
+        format!("Data, {} bytes", data.len())
+    }
+}
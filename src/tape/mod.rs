@@ -0,0 +1,50 @@
+mod block;
+mod tap;
+mod tzx;
+mod player;
+
+pub use tape::block::*;
+pub use tape::tap::*;
+pub use tape::tzx::*;
+pub use tape::player::*;
+
+use utils::read_bin;
+
+use std::path::Path;
+
+// The blocks of a tape image, whatever format it came in
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tape {
+    blocks: Vec<Block>,
+}
+
+impl Tape {
+    pub fn new(blocks: Vec<Block>) -> Self {
+        Tape { blocks }
+    }
+
+    // The format follows the extension
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let ext = path.extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase());
+        match ext.as_ref().map(|ext| &ext[..]) {
+            Some("tap") => parse_tap(&read_bin(path)),
+            Some("tzx") => parse_tzx(&read_bin(path)),
+            _ => Err(format!("Unknown tape format: {}", path.display())),
+        }
+    }
+
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+}
@@ -0,0 +1,278 @@
+use super::*;
+
+// Plays a tape into the EAR input, one block after the other. Each block is
+// turned into a list of levels and how long they last when playback gets to
+// it.
+pub struct TapePlayer {
+    tape: Tape,
+    // Block being played, or the next one to play
+    block: usize,
+    playing: bool,
+    cpu_hz: u32,
+    // Whether the machine is a 48K, for the blocks that stop the tape on
+    // one
+    is_48k: bool,
+    // First block and the number of times left to play of the loops being
+    // played
+    loops: Vec<(usize, u16)>,
+
+    level: bool,
+    // Signal of the current block, in T states at 3.5MHz, and how far
+    // into it playback is
+    signal: Vec<(u32, bool)>,
+    position: usize,
+    // CPU T states left of the current level
+    remaining: u64,
+    // Fraction of a CPU T state carried from one level to the next, in
+    // tape clock units
+    remainder: u64,
+}
+
+impl TapePlayer {
+    pub fn new(tape: Tape) -> Self {
+        TapePlayer {
+            tape,
+            block: 0,
+            playing: false,
+            cpu_hz: TAPE_CLOCK_HZ,
+            is_48k: true,
+            loops: Vec::new(),
+            level: false,
+            signal: Vec::new(),
+            position: 0,
+            remaining: 0,
+            remainder: 0,
+        }
+    }
+
+    pub fn tape(&self) -> &Tape {
+        &self.tape
+    }
+
+    // Clock of the CPU whose T states playback is measured in
+    pub fn set_clock(&mut self, cpu_hz: u32) {
+        self.cpu_hz = cpu_hz;
+    }
+
+    pub fn set_48k(&mut self, val: bool) {
+        self.is_48k = val;
+    }
+
+    pub fn play(&mut self) {
+        if self.block < self.tape.len() {
+            self.playing = true;
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn level(&self) -> bool {
+        self.level
+    }
+
+    // Index of the block being played, the number of blocks at the end of
+    // the tape
+    pub fn block(&self) -> usize {
+        self.block
+    }
+
+    // Wind the tape to the start of a block
+    pub fn set_block(&mut self, block: usize) {
+        assert!(block <= self.tape.len(), "The tape only has {} blocks", self.tape.len());
+        self.block = block;
+        self.loops.clear();
+        self.signal.clear();
+        self.position = 0;
+        self.remaining = 0;
+        if block == self.tape.len() {
+            self.playing = false;
+        }
+    }
+
+    pub fn rewind(&mut self) {
+        self.set_block(0);
+    }
+
+    pub fn is_at_end(&self) -> bool {
+        self.block >= self.tape.len()
+    }
+
+    // Bytes of the next data block on, for loading them straight into
+    // memory. The tape is wound past it.
+    pub fn take_data_block(&mut self) -> Option<Vec<u8>> {
+        let start = if self.position == 0 { self.block } else { self.block + 1 };
+        let found = self.tape.blocks()[start.min(self.tape.len())..].iter()
+            .position(|block| match *block {
+                Block::Data(_) => true,
+                _ => false,
+            });
+        found.map(|i| {
+            let block = start + i;
+            let data = match self.tape.blocks()[block] {
+                Block::Data(ref data) => data.data.clone(),
+                _ => unreachable!(),
+            };
+            self.set_block(block + 1);
+            data
+        })
+    }
+
+    // Play for tcycles T states, returning the changes of level with the
+    // T state they happen at
+    pub fn run(&mut self, tcycles: u32) -> Vec<(u32, bool)> {
+        let mut edges = Vec::new();
+        let mut t = 0;
+        while self.playing && t < tcycles {
+            if self.remaining == 0 {
+                let level = self.level;
+                if !self.next_level() {
+                    self.playing = false;
+                    break;
+                }
+                if self.level != level {
+                    edges.push((t, self.level));
+                }
+                continue;
+            }
+            let step = self.remaining.min((tcycles - t) as u64);
+            t += step as u32;
+            self.remaining -= step;
+        }
+        edges
+    }
+
+    // Move on to the next level of the signal, going through the blocks.
+    // Returns false at the end of the tape and at blocks that stop it, and
+    // also when jumps go round blocks that have nothing to play, as that
+    // would go on forever.
+    fn next_level(&mut self) -> bool {
+        let mut visited = 0;
+        while self.position >= self.signal.len() {
+            if self.position > 0 {
+                visited += 1;
+                if visited > self.tape.len() {
+                    return false;
+                }
+                self.next_block();
+            }
+            if self.block >= self.tape.len() {
+                self.signal.clear();
+                return false;
+            }
+
+            self.signal.clear();
+            // Whatever happens the block is done with
+            self.position = 1;
+            match self.tape.blocks()[self.block] {
+                Block::Pause(0) => return false,
+                Block::StopIf48K if self.is_48k => return false,
+                Block::SetLevel(level) => self.level = level,
+                _ => {
+                    self.signal = self.block_signal(self.block);
+                    self.position = 0;
+                    if self.signal.is_empty() {
+                        self.position = 1;
+                    }
+                }
+            }
+        }
+
+        let (length, level) = self.signal[self.position];
+        self.position += 1;
+        self.level = level;
+
+        let length = length as u64 * self.cpu_hz as u64 + self.remainder;
+        self.remaining = length / TAPE_CLOCK_HZ as u64;
+        self.remainder = length % TAPE_CLOCK_HZ as u64;
+        true
+    }
+
+    // Leave the current block for the one that follows, which jumps and
+    // loops decide on
+    fn next_block(&mut self) {
+        self.position = 0;
+        match self.tape.blocks()[self.block] {
+            // A jump to itself is taken as a jump to the next block
+            Block::Jump(0) => self.block += 1,
+            Block::Jump(offset) => {
+                let block = self.block as isize + offset as isize;
+                self.block = if block < 0 { 0 } else { block as usize };
+            }
+            Block::LoopStart(count) => {
+                self.block += 1;
+                self.loops.push((self.block, count));
+            }
+            Block::LoopEnd => {
+                self.block += 1;
+                if let Some((start, left)) = self.loops.pop() {
+                    if left > 1 {
+                        self.loops.push((start, left - 1));
+                        self.block = start;
+                    }
+                }
+            }
+            _ => self.block += 1,
+        }
+        if self.block > self.tape.len() {
+            self.block = self.tape.len();
+        }
+    }
+
+    // Levels of the block and how long each one lasts, in T states at
+    // 3.5MHz. Pulses flip the level, pauses are low.
+    fn block_signal(&self, block: usize) -> Vec<(u32, bool)> {
+        let mut level = self.level;
+        let mut signal = Vec::new();
+        let mut pulses = |signal: &mut Vec<(u32, bool)>, pulses: &[u32]| {
+            for &pulse in pulses {
+                level = !level;
+                signal.push((pulse, level));
+            }
+        };
+        match self.tape.blocks()[block] {
+            Block::Data(ref data) => {
+                pulses(&mut signal, &data.pulses());
+                if data.pause_ms > 0 {
+                    signal.push((data.pause_ms * TSTATES_PER_MS, false));
+                }
+            }
+            Block::PureTone { pulse, count } => {
+                pulses(&mut signal, &vec![pulse; count as usize]);
+            }
+            Block::Pulses(ref lengths) => {
+                pulses(&mut signal, lengths);
+            }
+            Block::DirectRecording { tstates_per_sample, pause_ms, last_byte_bits, ref data } => {
+                for (i, &byte) in data.iter().enumerate() {
+                    let bits = if i + 1 == data.len() { last_byte_bits } else { 8 };
+                    for bit in 0..bits {
+                        let level = byte & (0x80 >> bit) != 0;
+                        // Runs of the same level make a single one
+                        match signal.last_mut() {
+                            Some(&mut (ref mut length, last)) if last == level => {
+                                *length += tstates_per_sample;
+                                continue;
+                            }
+                            _ => {}
+                        }
+                        signal.push((tstates_per_sample, level));
+                    }
+                }
+                if pause_ms > 0 {
+                    signal.push((pause_ms * TSTATES_PER_MS, false));
+                }
+            }
+            Block::Pause(ms) => {
+                signal.push((ms * TSTATES_PER_MS, false));
+            }
+            _ => {}
+        }
+        signal
+    }
+}
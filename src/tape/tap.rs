@@ -0,0 +1,21 @@
+use super::{Block, DataBlock, Tape};
+
+use nom::{IResult, le_u16};
+
+named!(
+    tap_blocks<&[u8], Vec<&[u8]>>,
+    many0!(complete!(length_data!(le_u16)))
+);
+
+// A .tap file is nothing but the blocks as the ROM saves them, each after
+// its length. They all get the ROM's timings and a second of silence.
+pub fn parse_tap(input: &[u8]) -> Result<Tape, String> {
+    match tap_blocks(input) {
+        IResult::Done(rest, blocks) if rest.is_empty() => {
+            Ok(Tape::new(blocks.into_iter()
+                .map(|data| Block::Data(DataBlock::standard(data.to_vec(), 1000)))
+                .collect()))
+        }
+        _ => Err("Not a valid .tap file".to_string()),
+    }
+}
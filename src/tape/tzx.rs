@@ -0,0 +1,174 @@
+use super::{Block, DataBlock, Tape};
+
+use nom::{IResult, be_u8, le_u16, le_i16, le_u32};
+
+named!(
+    le_u24<&[u8], u32>,
+    do_parse!(
+      low: le_u16 >>
+      high: be_u8 >>
+      (low as u32 | (high as u32) << 16)
+    )
+);
+
+fn text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+named!(
+    turbo_speed_data<&[u8], Block>,
+    do_parse!(
+      pilot_pulse: le_u16 >>
+      sync1_pulse: le_u16 >>
+      sync2_pulse: le_u16 >>
+      zero_pulse: le_u16 >>
+      one_pulse: le_u16 >>
+      pilot_pulses: le_u16 >>
+      last_byte_bits: be_u8 >>
+      pause_ms: le_u16 >>
+      data: length_data!(le_u24) >>
+      (Block::Data(DataBlock {
+          pilot_pulse: pilot_pulse as u32,
+          pilot_pulses: pilot_pulses as u32,
+          sync1_pulse: sync1_pulse as u32,
+          sync2_pulse: sync2_pulse as u32,
+          zero_pulse: zero_pulse as u32,
+          one_pulse: one_pulse as u32,
+          last_byte_bits,
+          pause_ms: pause_ms as u32,
+          data: data.to_vec(),
+      }))
+    )
+);
+
+named!(
+    pure_data<&[u8], Block>,
+    do_parse!(
+      zero_pulse: le_u16 >>
+      one_pulse: le_u16 >>
+      last_byte_bits: be_u8 >>
+      pause_ms: le_u16 >>
+      data: length_data!(le_u24) >>
+      (Block::Data(DataBlock {
+          pilot_pulse: 0,
+          pilot_pulses: 0,
+          sync1_pulse: 0,
+          sync2_pulse: 0,
+          zero_pulse: zero_pulse as u32,
+          one_pulse: one_pulse as u32,
+          last_byte_bits,
+          pause_ms: pause_ms as u32,
+          data: data.to_vec(),
+      }))
+    )
+);
+
+named!(
+    tzx_block<&[u8], Block>,
+    switch!(be_u8,
+      // Standard speed data
+      0x10 => do_parse!(
+        pause_ms: le_u16 >>
+        data: length_data!(le_u16) >>
+        (Block::Data(DataBlock::standard(data.to_vec(), pause_ms as u32)))
+      ) |
+      0x11 => call!(turbo_speed_data) |
+      0x12 => do_parse!(
+        pulse: le_u16 >>
+        count: le_u16 >>
+        (Block::PureTone { pulse: pulse as u32, count: count as u32 })
+      ) |
+      0x13 => do_parse!(
+        pulses: length_count!(be_u8, le_u16) >>
+        (Block::Pulses(pulses.into_iter().map(|pulse| pulse as u32).collect()))
+      ) |
+      0x14 => call!(pure_data) |
+      0x15 => do_parse!(
+        tstates_per_sample: le_u16 >>
+        pause_ms: le_u16 >>
+        last_byte_bits: be_u8 >>
+        data: length_data!(le_u24) >>
+        (Block::DirectRecording {
+            tstates_per_sample: tstates_per_sample as u32,
+            pause_ms: pause_ms as u32,
+            last_byte_bits,
+            data: data.to_vec(),
+        })
+      ) |
+      // CSW recording and generalized data are left out
+      0x18 => map!(length_data!(le_u32), |_| Block::Info("CSW recording, not supported".to_string())) |
+      0x19 => map!(length_data!(le_u32), |_| Block::Info("Generalized data, not supported".to_string())) |
+      0x20 => map!(le_u16, |ms| Block::Pause(ms as u32)) |
+      0x21 => map!(length_data!(be_u8), |name| Block::GroupStart(text(name))) |
+      0x22 => value!(Block::GroupEnd) |
+      0x23 => map!(le_i16, Block::Jump) |
+      0x24 => map!(le_u16, Block::LoopStart) |
+      0x25 => value!(Block::LoopEnd) |
+      // Calls are left out, playback goes straight on
+      0x26 => map!(length_count!(le_u16, le_i16), |_| Block::Info("Call sequence, not supported".to_string())) |
+      0x27 => value!(Block::Info("Return from sequence, not supported".to_string())) |
+      0x28 => map!(length_data!(le_u16), |_| Block::Info("Select block".to_string())) |
+      0x2A => map!(le_u32, |_| Block::StopIf48K) |
+      0x2B => do_parse!(
+        le_u32 >>
+        level: be_u8 >>
+        (Block::SetLevel(level != 0))
+      ) |
+      0x30 => map!(length_data!(be_u8), |description| Block::Info(text(description))) |
+      0x31 => do_parse!(
+        be_u8 >>
+        message: length_data!(be_u8) >>
+        (Block::Info(text(message)))
+      ) |
+      0x32 => map!(length_data!(le_u16), |_| Block::Info("Archive info".to_string())) |
+      0x33 => do_parse!(
+        count: be_u8 >>
+        take!(3 * count as usize) >>
+        (Block::Info("Hardware type".to_string()))
+      ) |
+      0x34 => map!(take!(8), |_| Block::Info("Emulation info".to_string())) |
+      0x35 => do_parse!(
+        id: take!(16) >>
+        length_data!(le_u32) >>
+        (Block::Info(format!("Custom info: {}", text(id).trim_end())))
+      ) |
+      0x40 => do_parse!(
+        be_u8 >>
+        length_data!(le_u24) >>
+        (Block::Info("Snapshot, not supported".to_string()))
+      ) |
+      // Glue between concatenated files
+      0x5A => map!(take!(9), |_| Block::Info("Glue".to_string())) |
+      // Any other block starts with its length, so that it can be skipped
+      id => map!(length_data!(le_u32), |_| Block::Info(format!("Unknown block 0x{:02x}", id)))
+    )
+);
+
+named!(
+    tzx<&[u8], Vec<Block>>,
+    do_parse!(
+      tag!( &b"ZXTape!\x1A"[..] ) >>
+      // Major and minor version
+      verify!(be_u8, |major: u8| major == 1) >>
+      be_u8 >>
+      blocks: many0!(complete!(tzx_block)) >>
+      (blocks)
+    )
+);
+
+// Every block is kept, even those with nothing to play, since jumps count
+// blocks
+pub fn parse_tzx(input: &[u8]) -> Result<Tape, String> {
+    match tzx(input) {
+        IResult::Done(rest, blocks) => {
+            if rest.is_empty() {
+                Ok(Tape::new(blocks))
+            } else {
+                Err(format!("Unsupported or broken TZX block 0x{:02x} at offset {}",
+                            rest[0], input.len() - rest.len()))
+            }
+        }
+        _ => Err("Not a valid .tzx file".to_string()),
+    }
+}
+
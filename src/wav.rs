@@ -0,0 +1,79 @@
+use std::io;
+use std::io::{Seek, SeekFrom, Write};
+
+// 16 bit PCM WAV. The sizes in the header are only known once all the
+// samples are in, so they are filled in by finish(), and so is the sample
+// rate, which can still change after the first samples.
+pub struct WavWriter<W: Write + Seek> {
+    out: W,
+    sample_rate: u32,
+    channels: u16,
+    data_bytes: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut out: W, sample_rate: u32, channels: u16) -> io::Result<Self> {
+        let block_align = channels * 2;
+        out.write_all(b"RIFF")?;
+        out.write_all(&le_u32(0))?;
+        out.write_all(b"WAVE")?;
+
+        out.write_all(b"fmt ")?;
+        out.write_all(&le_u32(16))?;
+        out.write_all(&le_u16(1))?;
+        out.write_all(&le_u16(channels))?;
+        out.write_all(&le_u32(sample_rate))?;
+        out.write_all(&le_u32(sample_rate * block_align as u32))?;
+        out.write_all(&le_u16(block_align))?;
+        out.write_all(&le_u16(16))?;
+
+        out.write_all(b"data")?;
+        out.write_all(&le_u32(0))?;
+
+        Ok(WavWriter { out, sample_rate, channels, data_bytes: 0 })
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    // Interleaved when there is more than one channel
+    pub fn write_samples(&mut self, samples: &[i16]) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(samples.len() * 2);
+        for &sample in samples {
+            bytes.extend_from_slice(&le_u16(sample as u16));
+        }
+        self.out.write_all(&bytes)?;
+        self.data_bytes += bytes.len() as u32;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.seek(SeekFrom::Start(4))?;
+        self.out.write_all(&le_u32(36 + self.data_bytes))?;
+        self.out.seek(SeekFrom::Start(24))?;
+        self.out.write_all(&le_u32(self.sample_rate))?;
+        self.out.write_all(&le_u32(self.sample_rate * self.channels as u32 * 2))?;
+        self.out.seek(SeekFrom::Start(40))?;
+        self.out.write_all(&le_u32(self.data_bytes))?;
+        self.out.seek(SeekFrom::End(0))?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+fn le_u16(val: u16) -> [u8; 2] {
+    [val as u8, (val >> 8) as u8]
+}
+
+fn le_u32(val: u32) -> [u8; 4] {
+    [val as u8, (val >> 8) as u8, (val >> 16) as u8, (val >> 24) as u8]
+}
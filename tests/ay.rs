@@ -0,0 +1,162 @@
+extern crate z80emulib;
+
+#[cfg(test)]
+mod test_ay {
+
+    use z80emulib::peripherals::*;
+
+    const FRAME: u32 = 70908;
+
+    // One sample per T state, so that the output can be looked at exactly
+    fn ay() -> Ay {
+        let mut ay = Ay::new();
+        ay.set_clock(1_773_450, 3_546_900);
+        ay.set_sample_rate(3_546_900);
+        ay
+    }
+
+    fn write(ay: &mut Ay, register: u8, val: u8) {
+        ay.write_port(AY_SELECT_PORT, register);
+        ay.write_port(AY_DATA_PORT, val);
+    }
+
+    fn channel_a(ay: &mut Ay, tcycles: u32) -> Vec<u16> {
+        ay.end_frame(tcycles);
+        ay.take_samples().iter().map(|levels| levels[0]).collect()
+    }
+
+    #[test]
+    fn test_register_masks() {
+        let mut ay = ay();
+        let masks = [0xFF, 0x0F, 0xFF, 0x0F, 0xFF, 0x0F, 0x1F, 0xFF,
+                     0x1F, 0x1F, 0x1F, 0xFF, 0xFF, 0x0F, 0xFF, 0xFF];
+        for register in 0..16 {
+            write(&mut ay, register, 0xFF);
+            assert_eq!(ay.read_port(AY_SELECT_PORT), masks[register as usize]);
+        }
+
+        // Selecting a register past the last one is ignored
+        ay.write_port(AY_SELECT_PORT, 0x10);
+        assert_eq!(ay.read_port(AY_SELECT_PORT), 0xFF);
+        ay.write_port(AY_SELECT_PORT, 1);
+        assert_eq!(ay.read_port(AY_SELECT_PORT), 0x0F);
+    }
+
+    #[test]
+    fn test_levels() {
+        // With tone and noise off the volume comes straight out
+        for volume in 0..16 {
+            let mut ay = ay();
+            write(&mut ay, 7, 0x3F);
+            write(&mut ay, 8, volume);
+            let output = channel_a(&mut ay, 100);
+            assert!(output.iter().all(|&level| level == AY_LEVELS[volume as usize]));
+        }
+        assert_eq!(AY_LEVELS[0], 0);
+        assert_eq!(AY_LEVELS[15], 0xFFFF);
+    }
+
+    #[test]
+    fn test_tone_frequency() {
+        let mut ay = ay();
+        // 1773450 / (16 * 100) = 1108.4Hz on tone A only
+        write(&mut ay, 0, 100);
+        write(&mut ay, 1, 0);
+        write(&mut ay, 7, 0x3E);
+        write(&mut ay, 8, 15);
+
+        let output = channel_a(&mut ay, FRAME);
+        let rising_edges = output.windows(2).filter(|w| w[0] == 0 && w[1] != 0).count();
+        // A 50Hz frame holds 22.16 periods
+        assert_eq!(rising_edges, 22);
+
+        // Every half period lasts 8 * 100 AY clocks, 1600 T states
+        let first = output.iter().position(|&level| level != 0).unwrap();
+        assert!(output[first..first + 1600].iter().all(|&level| level == 0xFFFF));
+        assert_eq!(output[first + 1600], 0);
+    }
+
+    #[test]
+    fn test_register_write_timing() {
+        let mut ay = ay();
+        write(&mut ay, 7, 0x3F);
+        ay.write_port_at(AY_SELECT_PORT, 8, 0);
+        ay.write_port_at(AY_DATA_PORT, 15, 1000);
+        let output = channel_a(&mut ay, 2000);
+        assert_eq!(output[999], 0);
+        assert_eq!(output[1000], 0xFFFF);
+    }
+
+    #[test]
+    fn test_write_past_frame_end() {
+        // A write in the last instruction of the frame lands after its end
+        let mut ay = ay();
+        write(&mut ay, 7, 0x3F);
+        ay.write_port_at(AY_SELECT_PORT, 8, FRAME + 10);
+        ay.write_port_at(AY_DATA_PORT, 15, FRAME + 20);
+        let first = channel_a(&mut ay, FRAME);
+        let second = channel_a(&mut ay, FRAME);
+
+        // The 20 T states past the end were played with the first frame and
+        // aren't played again
+        assert_eq!(first.len(), FRAME as usize + 20);
+        assert_eq!(second.len(), FRAME as usize - 20);
+        assert!(first.iter().all(|&level| level == 0));
+        assert!(second.iter().all(|&level| level == 0xFFFF));
+    }
+
+    #[test]
+    fn test_envelope_shapes() {
+        // Envelope period 1: one step every 16 AY clocks, 32 T states
+        let check = |shape: u8, expected: &[u8]| {
+            let mut ay = ay();
+            write(&mut ay, 7, 0x3F);
+            write(&mut ay, 8, 0x10);
+            write(&mut ay, 11, 1);
+            write(&mut ay, 13, shape);
+            let output = channel_a(&mut ay, 64 * 32);
+            for (cycle, &level) in expected.iter().enumerate() {
+                // Middle of the last step of each 16 step cycle
+                let t = cycle * 16 * 32 + 15 * 32 + 16;
+                assert_eq!(output[t], AY_LEVELS[level as usize],
+                           "shape {:x} cycle {}", shape, cycle);
+            }
+            assert_eq!(output[16], AY_LEVELS[expected[0] as usize ^ 0x0F],
+                       "shape {:x} start", shape);
+        };
+
+        check(0x00, &[0, 0, 0, 0]);
+        check(0x08, &[0, 0, 0, 0]);
+        check(0x0A, &[0, 15, 0, 15]);
+        check(0x0B, &[0, 15, 15, 15]);
+        check(0x0C, &[15, 15, 15, 15]);
+        check(0x0E, &[15, 0, 15, 0]);
+        check(0x0D, &[15, 15, 15, 15]);
+        check(0x0F, &[15, 0, 0, 0]);
+        check(0x04, &[15, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_noise() {
+        let mut ay = ay();
+        write(&mut ay, 6, 1);
+        write(&mut ay, 7, 0x37);
+        write(&mut ay, 8, 15);
+        let output = channel_a(&mut ay, FRAME);
+        let changes = output.windows(2).filter(|w| w[0] != w[1]).count();
+        // Noise changes at most every 32 T states, and not in any pattern
+        // that stays on or off
+        assert!(changes > 100);
+        assert!(changes < FRAME as usize / 32);
+    }
+
+    #[test]
+    fn test_sample_rate() {
+        let mut ay = Ay::new();
+        ay.set_sample_rate(44100);
+        ay.end_frame(FRAME);
+        // 70908 T states at 3.5469MHz is 881.6 samples
+        assert_eq!(ay.take_samples().len(), 881);
+        assert!(ay.take_samples().is_empty());
+    }
+}
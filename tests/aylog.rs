@@ -0,0 +1,88 @@
+extern crate z80emulib;
+
+#[cfg(test)]
+mod test_aylog {
+
+    use z80emulib::aylog::*;
+    use z80emulib::peripherals::*;
+
+    fn log() -> AyLog {
+        let mut log = AyLog::new(1_773_450);
+        log.record(0, 0x10);
+        log.record(13, 0x0E);
+        log.end_frame();
+        log.record(8, 0x0F);
+        log.end_frame();
+        for _ in 0..5 {
+            log.end_frame();
+        }
+        log.record(0, 0x20);
+        log.end_frame();
+        // Not finished, left out
+        log.record(1, 0x01);
+        log
+    }
+
+    #[test]
+    fn test_ay_logging() {
+        let mut ay = Ay::new();
+        ay.start_log();
+        ay.write_port(AY_SELECT_PORT, 1);
+        ay.write_port(AY_DATA_PORT, 0xFF);
+        ay.end_frame(100);
+        ay.write_port(AY_DATA_PORT, 0x02);
+        ay.end_frame(100);
+
+        let log = ay.take_log().unwrap();
+        // Values as the chip keeps them
+        assert_eq!(log.frames(), &[vec![(1, 0x0F)], vec![(1, 0x02)]]);
+        assert!(ay.take_log().is_none());
+    }
+
+    #[test]
+    fn test_psg() {
+        let mut psg = Vec::new();
+        log().write_psg(&mut psg).unwrap();
+
+        assert_eq!(&psg[..4], b"PSG\x1A");
+        assert!(psg[4..16].iter().all(|&b| b == 0));
+        assert_eq!(&psg[16..], &[0x00, 0x10, 0x0D, 0x0E, 0xFF,
+                                 0x08, 0x0F, 0xFF,
+                                 0xFE, 0x01, 0xFF,
+                                 0x00, 0x20, 0xFF,
+                                 0xFD]);
+    }
+
+    #[test]
+    fn test_ym() {
+        let mut ym = Vec::new();
+        log().write_ym(&mut ym, 5).unwrap();
+
+        assert_eq!(&ym[..12], b"YM5!LeOnArD!");
+        // 8 frames, interleaved, no digidrums, AY clock, 50Hz, no loop
+        assert_eq!(&ym[12..34], &[0, 0, 0, 8,
+                                  0, 0, 0, 1,
+                                  0, 0,
+                                  0x00, 0x1B, 0x0F, 0x8A,
+                                  0, 50,
+                                  0, 0, 0, 0,
+                                  0, 0]);
+        let strings = b"\0\0Saved by rustz80emu\0";
+        assert_eq!(&ym[34..34 + strings.len()], &strings[..]);
+
+        let data = &ym[34 + strings.len()..];
+        assert_eq!(data.len(), 16 * 8 + 4);
+        assert_eq!(&data[16 * 8..], b"End!");
+        let register = |r: usize| &data[r * 8..(r + 1) * 8];
+        assert_eq!(register(0), &[0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x20]);
+        assert_eq!(register(8), &[0x00, 0x0F, 0x0F, 0x0F, 0x0F, 0x0F, 0x0F, 0x0F]);
+        // The envelope is only restarted where R13 was written
+        assert_eq!(register(13), &[0x0E, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(register(1), &[0; 8]);
+
+        let mut ym6 = Vec::new();
+        log().write_ym(&mut ym6, 6).unwrap();
+        assert_eq!(&ym6[..4], b"YM6!");
+        assert_eq!(&ym6[4..], &ym[4..]);
+    }
+}
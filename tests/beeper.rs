@@ -0,0 +1,69 @@
+extern crate z80emulib;
+
+#[cfg(test)]
+mod test_beeper {
+
+    use z80emulib::peripherals::*;
+    use z80emulib::machine::*;
+
+    fn rising_edges(samples: &[u16]) -> usize {
+        samples.windows(2).filter(|w| w[0] < 0x8000 && w[1] >= 0x8000).count()
+    }
+
+    #[test]
+    fn test_square_wave() {
+        let mut beeper = Beeper::new();
+        beeper.set_clock(3_500_000);
+        beeper.set_sample_rate(44100);
+
+        // 1kHz for 20 frames of 70000 T states
+        let mut samples = Vec::new();
+        for _ in 0..20 {
+            for half in 0..40 {
+                let val = if half % 2 == 0 { 0x10 } else { 0x00 };
+                beeper.write(val, half * 1750);
+            }
+            assert_eq!(beeper.edges().len(), 40);
+            beeper.end_frame(70000);
+            samples.extend(beeper.take_samples());
+        }
+
+        // 0.4s at 44.1kHz, minus the first edge still on its way out
+        assert_eq!(samples.len(), 17640);
+        assert_eq!(rising_edges(&samples), 400);
+
+        // Edges are smoothed, not jumps from one sample to the next
+        assert!(samples.iter().any(|&s| s > 0x1000 && s < 0xE000));
+        // and EAR alone is a little under full scale
+        assert!(samples.iter().any(|&s| s > 0xF000 && s < 0xF800));
+    }
+
+    #[test]
+    fn test_tone_routine() {
+        let mut rom = vec![0; 16 * 1024];
+        rom[..14].copy_from_slice(&[
+            0xF3,               // DI
+            0xAF,               // XOR A
+            0xEE, 0x10,         // XOR 10h        ; 7
+            0xD3, 0xFE,         // OUT (FEh),A    ; 11
+            0x06, 0x64,         // LD B,100       ; 7
+            0x10, 0xFE,         // DJNZ $         ; 13 * 99 + 8
+            0x18, 0xF6,         // JR -10         ; 12
+            0x00, 0x00]);
+        let mut machine = Machine::with_roms(false, Model::Spectrum48K, 48, vec![rom.into_boxed_slice()]);
+
+        let mut samples = Vec::new();
+        for _ in 0..50 {
+            machine.run_frame();
+            samples.extend(machine.audio_samples());
+        }
+        // Left channel only, back to the beeper's own scale. With no AY
+        // fitted it has half the output to itself.
+        let samples: Vec<u16> = samples.chunks(2).map(|frame| (frame[0] as u16) * 2).collect();
+
+        // Each half period is 1332 T states plus a little port contention,
+        // which at 3.5MHz is 1313.8Hz, over 50 frames of 69888 T states
+        let edges = rising_edges(&samples);
+        assert!(edges > 1300 && edges <= 1312, "{}", edges);
+    }
+}
@@ -0,0 +1,90 @@
+extern crate z80emulib;
+
+#[cfg(test)]
+mod test_border {
+
+    use z80emulib::peripherals::*;
+    use z80emulib::interconnect::*;
+
+    use std::rc::Rc;
+    use std::cell::RefCell;
+
+    // Beam position at the top left corner of the display on a 128K
+    const DISPLAY_START: u32 = 14362 - 48 * 228 - 24;
+
+    fn ula() -> (Interconnect, Rc<RefCell<Ula>>) {
+        let memory = Rc::new(RefCell::new(MemoryBuilder::new().finalize()));
+        let ay = Rc::new(RefCell::new(Ay::new()));
+        let ula = Rc::new(RefCell::new(Ula::new(memory.clone())));
+        (Interconnect::new(memory.clone(), ay, ula.clone()), ula)
+    }
+
+    fn pixel(ula: &Rc<RefCell<Ula>>, x: usize, y: usize) -> u8 {
+        ula.borrow().pixels()[y * DISPLAY_WIDTH + x]
+    }
+
+    #[test]
+    fn test_border_changes() {
+        let (interconnect, ula) = ula();
+
+        // Loading stripes: red, then cyan with the speaker bit set
+        interconnect.write_port(0x00FE, 0x02, DISPLAY_START + 10 * 228);
+        interconnect.write_port(0x00FE, 0x15, DISPLAY_START + 20 * 228 + 50);
+        assert_eq!(ula.borrow().get_border(), 5);
+        ula.borrow_mut().end_frame();
+
+        assert_eq!(pixel(&ula, 0, 9), 7);
+        assert_eq!(pixel(&ula, 0, 10), 2);
+        assert_eq!(pixel(&ula, 99, 20), 2);
+        assert_eq!(pixel(&ula, 100, 20), 5);
+        assert_eq!(pixel(&ula, DISPLAY_WIDTH - 1, DISPLAY_HEIGHT - 1), 5);
+
+        // The next frame starts with the last colour
+        ula.borrow_mut().end_frame();
+        assert_eq!(pixel(&ula, 0, 0), 5);
+    }
+
+    #[test]
+    fn test_multicolour() {
+        let (interconnect, ula) = ula();
+
+        // White paper for the top left cell, red from its fifth line on
+        interconnect.write_word(0x5800, 0x38, 0);
+        interconnect.write_word(0x5800, 0x10, 14362 + 4 * 228 - 10);
+        ula.borrow_mut().end_frame();
+
+        let cell: Vec<u8> = (0..8).map(|y| pixel(&ula, BORDER_LEFT, BORDER_TOP + y)).collect();
+        assert_eq!(cell, vec![7, 7, 7, 7, 2, 2, 2, 2]);
+    }
+
+    #[test]
+    fn test_screen_paged_high() {
+        let (interconnect, ula) = ula();
+
+        // The same cell changed through bank 5 paged in at 0xC000
+        interconnect.write_port(0x7ffd, 0x05, 0);
+        interconnect.write_word(0xD800, 0x38, 0);
+        interconnect.write_word(0xD800, 0x10, 14362 + 4 * 228 - 10);
+        ula.borrow_mut().end_frame();
+
+        let cell: Vec<u8> = (0..8).map(|y| pixel(&ula, BORDER_LEFT, BORDER_TOP + y)).collect();
+        assert_eq!(cell, vec![7, 7, 7, 7, 2, 2, 2, 2]);
+    }
+
+    #[test]
+    fn test_turbo_clock() {
+        let (mut interconnect, ula) = ula();
+
+        // At twice the ULA clock the beam moves once every two CPU T states
+        interconnect.set_cpu_hz(Some(7_093_800));
+        interconnect.write_port(0x00FE, 0x02, 2 * (DISPLAY_START + 10 * 228));
+        interconnect.write_word(0x5800, 0x38, 0);
+        interconnect.write_word(0x5800, 0x10, 2 * (14362 + 4 * 228 - 10));
+        ula.borrow_mut().end_frame();
+
+        assert_eq!(pixel(&ula, 0, 9), 7);
+        assert_eq!(pixel(&ula, 0, 10), 2);
+        let cell: Vec<u8> = (0..8).map(|y| pixel(&ula, BORDER_LEFT, BORDER_TOP + y)).collect();
+        assert_eq!(cell, vec![7, 7, 7, 7, 2, 2, 2, 2]);
+    }
+}
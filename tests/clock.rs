@@ -0,0 +1,108 @@
+extern crate z80emulib;
+
+#[cfg(test)]
+mod test_clock {
+
+    use z80emulib::cpu::*;
+    use z80emulib::peripherals::*;
+    use z80emulib::interconnect::*;
+    use z80emulib::machine::CpuClock;
+
+    use std::rc::Rc;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_frame_tcycles() {
+        assert_eq!(CpuClock::Mhz3_5.frame_tcycles(), Some(69970));
+        assert_eq!(CpuClock::Mhz3_5469.frame_tcycles(), Some(70908));
+        assert_eq!(CpuClock::Mhz7.frame_tcycles(), Some(2 * 70908));
+        assert_eq!(CpuClock::Mhz14.frame_tcycles(), Some(4 * 70908));
+        assert_eq!(CpuClock::Unlimited.frame_tcycles(), None);
+
+        assert_eq!("14".parse::<CpuClock>(), Ok(CpuClock::Mhz14));
+        assert!("4".parse::<CpuClock>().is_err());
+    }
+
+    // Spins in contended memory with interrupts enabled, wired up for the
+    // clock the way a 128K machine is
+    fn cpu(clock: CpuClock) -> Cpu {
+        let mut rom = vec![0; 16 * 1024];
+        rom[0..4].copy_from_slice(&[
+            0xFB,               // EI
+            0xC3, 0x00, 0x40]); // JP 4000h
+        let memory = Rc::new(RefCell::new(MemoryBuilder::new()
+                        .rom0(rom.into_boxed_slice())
+                        .finalize()));
+        memory.borrow_mut().write_word(0x4000, 0x18); // JR -2
+        memory.borrow_mut().write_word(0x4001, 0xFE);
+
+        let ay = Rc::new(RefCell::new(Ay::new()));
+        let ula = Rc::new(RefCell::new(Ula::new(memory.clone())));
+
+        let mut interconnect = Interconnect::new(
+            memory.clone(),
+            ay.clone(),
+            ula.clone());
+        interconnect.set_contention(clock.is_contended(&SPECTRUM_128K));
+        interconnect.set_cpu_hz(clock.hz().map(|hz| hz as u32));
+
+        let mut cpu = Cpu::new(interconnect);
+        cpu.set_im(1);
+        cpu.set_frame_tcycles(clock.frame_tcycles().unwrap());
+        cpu
+    }
+
+    // T state at which the first frame interrupt is accepted
+    fn first_interrupt(clock: CpuClock) -> u32 {
+        let mut cpu = cpu(clock);
+        cpu.run_instruction();
+        loop {
+            let tcycles = cpu.tcycles;
+            cpu.handle_interrupts();
+            if !cpu.get_iff1() {
+                return tcycles;
+            }
+            cpu.run_instruction();
+        }
+    }
+
+    #[test]
+    fn test_turbo_frame_interrupt() {
+        // Turbo frames run well past the end of a 3.5MHz frame, which is
+        // only possible with contention off
+        for &clock in [CpuClock::Mhz3_5469, CpuClock::Mhz7, CpuClock::Mhz14].iter() {
+            let frame_tcycles = clock.frame_tcycles().unwrap();
+            let tcycles = first_interrupt(clock);
+            assert!(tcycles >= frame_tcycles && tcycles < frame_tcycles + 12,
+                    "{:?}: interrupt at {}", clock, tcycles);
+        }
+    }
+
+    #[test]
+    fn test_slow_clock_contention() {
+        // Only clocks above the ULA's lose contention
+        assert!(CpuClock::Mhz3_5.is_contended(&SPECTRUM_128K));
+        assert!(CpuClock::Mhz3_5469.is_contended(&SPECTRUM_128K));
+        assert!(!CpuClock::Mhz7.is_contended(&SPECTRUM_128K));
+        assert!(!CpuClock::Unlimited.is_contended(&SPECTRUM_128K));
+        assert!(CpuClock::Mhz3_5.is_contended(&SPECTRUM_48K));
+        assert!(!CpuClock::Mhz3_5469.is_contended(&SPECTRUM_48K));
+
+        // At 3.5MHz on the 128K the JR at 4000h waits for the ULA when its
+        // first contended T state, 14361, comes round. That is CPU T state
+        // 14172 at the slower clock. Every access is held up as at the
+        // native clock, less the T state the ULA gains on the way.
+        let jr = |clock: CpuClock, tcycle: u32| {
+            let mut cpu = cpu(clock);
+            cpu.run_instruction();
+            cpu.run_instruction();
+            cpu.tcycles = tcycle;
+            cpu.run_instruction();
+            cpu.tcycles - tcycle
+        };
+        assert_eq!(jr(CpuClock::Mhz3_5, 1000), 12);
+        assert_eq!(jr(CpuClock::Mhz3_5469, 14361), 39);
+        assert_eq!(jr(CpuClock::Mhz3_5, 14172), 38);
+        assert_eq!(jr(CpuClock::Mhz7, 2 * 14361), 12);
+    }
+}
@@ -0,0 +1,60 @@
+extern crate z80emulib;
+
+#[cfg(test)]
+mod test_contention {
+
+    use z80emulib::peripherals::*;
+    use z80emulib::interconnect::*;
+
+    use std::rc::Rc;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_delay_pattern() {
+        assert_eq!(SPECTRUM_128K.delay(14360), 0);
+        let line: Vec<u32> = (14361..14369).map(|t| SPECTRUM_128K.delay(t)).collect();
+        assert_eq!(line, vec![6, 5, 4, 3, 2, 1, 0, 0]);
+        // Right border, then the next line
+        assert_eq!(SPECTRUM_128K.delay(14361 + 128), 0);
+        assert_eq!(SPECTRUM_128K.delay(14361 + 228), 6);
+        // Last display line, then the bottom border
+        assert_eq!(SPECTRUM_128K.delay(14361 + 191 * 228 + 127), 0);
+        assert_eq!(SPECTRUM_128K.delay(14361 + 191 * 228 + 126), 0);
+        assert_eq!(SPECTRUM_128K.delay(14361 + 191 * 228 + 120), 6);
+        assert_eq!(SPECTRUM_128K.delay(14361 + 192 * 228), 0);
+
+        assert_eq!(SPECTRUM_48K.delay(14335), 6);
+        assert_eq!(SPECTRUM_48K.delay(14335 + 224 + 1), 5);
+        assert_eq!(SPECTRUM_PLUS2A.delay(14365), 1);
+        assert_eq!(SPECTRUM_PLUS2A.delay(14367), 7);
+        assert!((0..PENTAGON.frame_tcycles).all(|t| PENTAGON.delay(t) == 0));
+    }
+
+    #[test]
+    fn test_out_of_range_tcycles() {
+        // Frames that overrun wrap around instead of running off a table
+        assert_eq!(SPECTRUM_128K.delay(70908 + 14361), 6);
+        assert_eq!(SPECTRUM_48K.delay(u32::max_value()), SPECTRUM_48K.delay(u32::max_value() % 69888));
+    }
+
+    #[test]
+    fn test_contended_banks() {
+        let memory = Rc::new(RefCell::new(MemoryBuilder::new().finalize()));
+        let ay = Rc::new(RefCell::new(Ay::new()));
+        let ula = Rc::new(RefCell::new(Ula::new(memory.clone())));
+        let mut interconnect = Interconnect::new(memory.clone(), ay, ula);
+
+        // Bank 7 at 0xC000 is contended on the 128K, but not on the +2A
+        interconnect.write_port(0x7ffd, 0x07, 0);
+        assert_eq!(interconnect.contend_read(0xC000, 14361, 3), 9);
+        assert_eq!(interconnect.contend_read(0x8000, 14361, 3), 3);
+
+        interconnect.set_ula_timing(SPECTRUM_PLUS2A);
+        assert_eq!(interconnect.contend_read(0xC000, 14365, 3), 4);
+        interconnect.write_port(0x7ffd, 0x03, 0);
+        assert_eq!(interconnect.contend_read(0xC000, 14365, 3), 3);
+
+        interconnect.set_ula_timing(PENTAGON);
+        assert_eq!(interconnect.contend_read(0x4000, 14361, 3), 3);
+    }
+}
@@ -0,0 +1,129 @@
+extern crate z80emulib;
+
+#[cfg(test)]
+mod test_cpm {
+
+    use z80emulib::cpm::*;
+
+    use std::env;
+    use std::fs;
+    use std::io;
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::process;
+
+    use std::rc::Rc;
+    use std::cell::RefCell;
+
+    #[derive(Clone)]
+    struct Console(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Console {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("z80cpm-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn run(program: &[u8], root: &PathBuf, args: &[&str]) -> String {
+        let console = Console(Rc::new(RefCell::new(Vec::new())));
+
+        let mut machine = CpmMachine::new(program, root);
+        machine.set_console(Box::new(io::empty()), Box::new(console.clone()));
+        machine.set_args(args);
+        machine.run();
+
+        let output = console.0.borrow().clone();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_print_string() {
+        let root = scratch_dir("print");
+        let program = [
+            0x11, 0x09, 0x01,   // LD DE,0109h
+            0x0E, 0x09,         // LD C,9
+            0xCD, 0x05, 0x00,   // CALL 5
+            0xC9,               // RET
+            b'H', b'i', b'$'];
+
+        assert_eq!(run(&program, &root, &[]), "Hi");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_command_tail() {
+        let root = scratch_dir("tail");
+        let program = [
+            0x11, 0x81, 0x00,   // LD DE,0081h
+            0x21, 0x80, 0x00,   // LD HL,0080h
+            0x4E,               // LD C,(HL)
+            0x06, 0x00,         // LD B,0
+            0x09,               // ADD HL,BC
+            0x23,               // INC HL
+            0x36, b'$',         // LD (HL),'$'
+            0x0E, 0x09,         // LD C,9
+            0xC3, 0x05, 0x00];  // JP 5
+
+        assert_eq!(run(&program, &root, &["foo.txt", "b:*.com"]), " FOO.TXT B:*.COM");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_file_roundtrip() {
+        let root = scratch_dir("files");
+
+        let mut writer = vec![
+            0x11, 0x5C, 0x00,   // LD DE,005Ch
+            0x0E, 0x16,         // LD C,22
+            0xCD, 0x05, 0x00,   // CALL 5
+            0x11, 0x00, 0x02,   // LD DE,0200h
+            0x0E, 0x1A,         // LD C,26
+            0xCD, 0x05, 0x00,   // CALL 5
+            0x11, 0x5C, 0x00,   // LD DE,005Ch
+            0x0E, 0x15,         // LD C,21
+            0xCD, 0x05, 0x00,   // CALL 5
+            0x11, 0x5C, 0x00,   // LD DE,005Ch
+            0x0E, 0x10,         // LD C,16
+            0xCD, 0x05, 0x00,   // CALL 5
+            0xC9];              // RET
+        writer.resize(0x100, 0x00);
+        writer.extend_from_slice(b"Hello, CP/M$");
+        writer.resize(0x180, 0x1A);
+
+        run(&writer, &root, &["hello.txt"]);
+
+        let contents = fs::read(root.join("hello.txt")).unwrap();
+        assert_eq!(contents.len(), 128);
+        assert_eq!(&contents[0..12], b"Hello, CP/M$");
+
+        let reader = [
+            0x11, 0x5C, 0x00,   // LD DE,005Ch
+            0x0E, 0x0F,         // LD C,15
+            0xCD, 0x05, 0x00,   // CALL 5
+            0x11, 0x5C, 0x00,   // LD DE,005Ch
+            0x0E, 0x14,         // LD C,20
+            0xCD, 0x05, 0x00,   // CALL 5
+            0x11, 0x80, 0x00,   // LD DE,0080h
+            0x0E, 0x09,         // LD C,9
+            0xCD, 0x05, 0x00,   // CALL 5
+            0xC9];              // RET
+
+        assert_eq!(run(&reader, &root, &["HELLO.TXT"]), "Hello, CP/M");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
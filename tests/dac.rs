@@ -0,0 +1,90 @@
+extern crate z80emulib;
+
+#[cfg(test)]
+mod test_dac {
+
+    use z80emulib::peripherals::*;
+    use z80emulib::machine::*;
+
+    #[test]
+    fn test_ramp() {
+        let mut dac = Dac::new();
+        // 10 T states per sample
+        dac.set_clock(441_000);
+        dac.set_sample_rate(44100);
+
+        for val in 0..256 {
+            dac.write_port(SPECDRUM_PORT, val as u8);
+            dac.clock(10);
+        }
+        assert_eq!(dac.writes().len(), 256);
+        assert_eq!(dac.writes()[1], (10, 1));
+
+        dac.end_frame(2560);
+        let samples = dac.take_samples();
+        let ramp: Vec<u16> = (0..256).map(|val| val * 0x101).collect();
+        assert_eq!(samples, ramp);
+
+        // The last value is held into the next frame
+        dac.end_frame(20);
+        assert_eq!(dac.take_samples(), vec![0xFFFF, 0xFFFF]);
+    }
+
+    #[test]
+    fn test_write_timing() {
+        let mut dac = Dac::new();
+        dac.set_clock(441_000);
+        dac.set_sample_rate(44100);
+
+        // Samples average what was held over their 10 T states
+        dac.write(0x00, 0);
+        dac.write(0xFF, 15);
+        dac.end_frame(30);
+        assert_eq!(dac.take_samples(), vec![0x0000, 0x7FFF, 0xFFFF]);
+    }
+
+    #[test]
+    fn test_specdrum_ramp() {
+        let mut rom = vec![0; 16 * 1024];
+        rom[..12].copy_from_slice(&[
+            0xF3,               // DI
+            0xAF,               // XOR A
+            0xD3, 0xDF,         // OUT (DFh),A    ; 11
+            0x3C,               // INC A          ; 4
+            0x06, 0xFF,         // LD B,255       ; 7
+            0x10, 0xFE,         // DJNZ $         ; 13 * 254 + 8
+            0x18, 0xF7,         // JR -9          ; 12
+            0x00]);
+        let mut machine = Machine::with_roms(false, Model::Spectrum48K, 48, vec![rom.into_boxed_slice()]);
+        machine.enable_specdrum();
+
+        machine.run_frame();
+        let samples = machine.audio_samples();
+        assert_eq!(samples.len(), 2 * machine.samples_per_frame());
+
+        // A rising step every 3344 T states, after the first sample which
+        // still has some of the 0x80 the DAC starts at. The beeper is silent
+        // and has as much of the output as the DAC.
+        let left: Vec<i16> = samples.chunks(2).map(|frame| frame[0]).collect();
+        assert!(left[0] > 0);
+        assert_eq!(left[1], 0);
+        assert!(left[1..].windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(*left.last().unwrap(), 20 * 0x101 / 4);
+        assert!(samples.chunks(2).all(|frame| frame[0] == frame[1]));
+    }
+
+    #[test]
+    fn test_covox_port() {
+        let mut rom = vec![0; 16 * 1024];
+        rom[..6].copy_from_slice(&[
+            0x3E, 0xFF,         // LD A,FFh
+            0xD3, 0xFB,         // OUT (FBh),A
+            0x18, 0xFE]);       // JR $
+        let mut machine = Machine::with_roms(false, Model::Spectrum48K, 48, vec![rom.into_boxed_slice()]);
+        machine.enable_covox();
+
+        machine.run_frame();
+        let samples = machine.audio_samples();
+        assert_eq!(*samples.last().unwrap(), (0xFFFF / 4) as i16);
+    }
+}
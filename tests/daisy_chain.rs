@@ -0,0 +1,155 @@
+extern crate z80emulib;
+
+#[cfg(test)]
+mod test_daisy_chain {
+
+    use z80emulib::cpu::*;
+    use z80emulib::peripherals::*;
+    use z80emulib::interconnect::*;
+
+    use std::io;
+    use std::io::Write;
+    use std::sync::mpsc::channel;
+
+    use std::rc::Rc;
+    use std::cell::RefCell;
+
+    #[derive(Clone)]
+    struct Line(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Line {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn flat_memory() -> Rc<RefCell<Memory>> {
+        Rc::new(RefCell::new(MemoryBuilder::new()
+            .writable_rom(true)
+            .finalize()))
+    }
+
+    fn load(memory: &Rc<RefCell<Memory>>, addr: u16, bytes: &[u8]) {
+        for (i, byte) in bytes.iter().enumerate() {
+            memory.borrow_mut().write_word(addr + i as u16, *byte);
+        }
+    }
+
+    #[test]
+    fn test_ctc_im2_timer() {
+        let memory = flat_memory();
+        load(&memory, 0x0000, &[
+            0x31, 0x00, 0xF0,   // LD SP,F000h
+            0x3E, 0x10,         // LD A,10h
+            0xED, 0x47,         // LD I,A
+            0xED, 0x5E,         // IM 2
+            0x3E, 0x20,         // LD A,20h      ; vector
+            0xD3, 0x00,         // OUT (00h),A
+            0x3E, 0x87,         // LD A,87h      ; interrupt, timer, /16
+            0xD3, 0x01,         // OUT (01h),A
+            0x3E, 0x0A,         // LD A,10       ; time constant
+            0xD3, 0x01,         // OUT (01h),A
+            0xFB,               // EI
+            0x76,               // HALT
+            0x18, 0xFD]);       // JR -3
+        load(&memory, 0x0100, &[
+            0x21, 0x00, 0x20,   // LD HL,2000h
+            0x34,               // INC (HL)
+            0xFB,               // EI
+            0xED, 0x4D]);       // RETI
+        // Channel 1 interrupts through the table entry at I:22h
+        load(&memory, 0x1022, &[0x00, 0x01]);
+
+        let ctc = Rc::new(RefCell::new(Ctc::new()));
+        let mut interconnect = Interconnect::without_peripherals(memory.clone());
+        interconnect.attach(ctc.clone(), 0x00FC, 0x0000);
+
+        let mut cpu = Cpu::new(interconnect);
+        while cpu.tcycles < 22 * 4 + 2 * 11 + 1600 {
+            cpu.handle_interrupts();
+            cpu.run_instruction();
+        }
+
+        // One interrupt every 160 T states once the time constant is loaded
+        let interrupts = memory.borrow().read_word(0x2000);
+        assert!(interrupts >= 9 && interrupts <= 10, "{} interrupts", interrupts);
+    }
+
+    #[test]
+    fn test_priority_and_reti() {
+        let ctc = Rc::new(RefCell::new(Ctc::new()));
+        let pio = Rc::new(RefCell::new(Pio::new()));
+
+        let mut interconnect = Interconnect::without_peripherals(flat_memory());
+        interconnect.attach(ctc.clone(), 0x00FC, 0x0000);
+        interconnect.attach(pio.clone(), 0x00FC, 0x0004);
+
+        // CTC channel 2 as a counter that interrupts on every edge
+        interconnect.write_port(0x0000, 0x40, 0);
+        interconnect.write_port(0x0002, 0xC5, 0);
+        interconnect.write_port(0x0002, 0x01, 0);
+
+        // PIO port A in input mode with interrupts enabled
+        interconnect.write_port(0x0006, 0x60, 0);
+        interconnect.write_port(0x0006, 0x4F, 0);
+        interconnect.write_port(0x0006, 0x87, 0);
+
+        assert!(!interconnect.interrupt_requested(false));
+
+        pio.borrow_mut().strobe(0, 0x55);
+        ctc.borrow_mut().trigger(2);
+        assert!(interconnect.interrupt_requested(false));
+
+        // The CTC is first in the chain, and masks the PIO while in service
+        assert_eq!(interconnect.interrupt_acknowledge(), 0x44);
+        assert!(!interconnect.interrupt_requested(false));
+
+        interconnect.reti();
+        assert!(interconnect.interrupt_requested(false));
+        assert_eq!(interconnect.interrupt_acknowledge(), 0x60);
+        assert_eq!(interconnect.read_port(0x0004, 0), 0x55);
+
+        interconnect.reti();
+        assert!(!interconnect.interrupt_requested(false));
+    }
+
+    #[test]
+    fn test_sio_receive_vector() {
+        let (sender, receiver) = channel();
+        let line = Line(Rc::new(RefCell::new(Vec::new())));
+
+        let sio = Rc::new(RefCell::new(Sio::new()));
+        sio.borrow_mut().connect(0, receiver, Box::new(line.clone()));
+
+        let mut interconnect = Interconnect::without_peripherals(flat_memory());
+        interconnect.attach(sio.clone(), 0x00FC, 0x0008);
+
+        // Channel A: interrupt on every received character, RX and TX on
+        for &val in [0x01, 0x18, 0x03, 0xC1, 0x05, 0x68].iter() {
+            interconnect.write_port(0x0009, val, 0);
+        }
+        // Channel B: vector 80h, modified by the interrupting source
+        for &val in [0x02, 0x80, 0x01, 0x04].iter() {
+            interconnect.write_port(0x000B, val, 0);
+        }
+
+        assert!(!interconnect.interrupt_requested(false));
+        sender.send(b'Z').unwrap();
+        assert!(interconnect.interrupt_requested(false));
+
+        // Channel A receive character available
+        assert_eq!(interconnect.interrupt_acknowledge(), 0x8C);
+        assert_eq!(interconnect.read_port(0x0009, 0) & 0x01, 0x01);
+        let received = interconnect.read_port(0x0008, 0);
+        interconnect.write_port(0x0008, received, 0);
+        interconnect.reti();
+
+        assert!(!interconnect.interrupt_requested(false));
+        assert_eq!(&line.0.borrow()[..], b"Z");
+    }
+}
@@ -0,0 +1,100 @@
+extern crate z80emulib;
+
+#[cfg(test)]
+mod test_ear {
+
+    use z80emulib::peripherals::*;
+    use z80emulib::interconnect::*;
+    use z80emulib::machine::*;
+
+    use std::rc::Rc;
+    use std::cell::RefCell;
+
+    fn ula() -> Ula {
+        let memory = Rc::new(RefCell::new(MemoryBuilder::new().finalize()));
+        Ula::new(memory)
+    }
+
+    #[test]
+    fn test_issue_feedback() {
+        let mut ula = ula();
+        assert_eq!(ula.get_issue(), Issue::Issue3);
+
+        // Only EAR counts on an issue 3
+        for &(val, issue3, issue2) in &[(0x00, 0xBF, 0xBF),
+                                        (0x08, 0xBF, 0xFF),
+                                        (0x10, 0xFF, 0xFF),
+                                        (0x18, 0xFF, 0xFF)] {
+            ula.write_port(0xfe, val);
+            ula.set_issue(Issue::Issue3);
+            assert_eq!(ula.read_port(0xfe), issue3, "{:02x}", val);
+            ula.set_issue(Issue::Issue2);
+            assert_eq!(ula.read_port(0xfe), issue2, "{:02x}", val);
+        }
+    }
+
+    #[test]
+    fn test_tape_signal() {
+        let mut ula = ula();
+        ula.write_port(0xfe, 0x10);
+
+        // The tape takes over from the feedback
+        ula.set_ear_input(Some(false));
+        assert_eq!(ula.read_port(0xfe), 0xBF);
+
+        ula.add_ear_edge(100, true);
+        ula.add_ear_edge(200, false);
+        assert_eq!(ula.read_port_at(0xfe, 99), 0xBF);
+        assert_eq!(ula.read_port_at(0xfe, 100), 0xFF);
+        assert_eq!(ula.read_port_at(0xfe, 150), 0xFF);
+        assert_eq!(ula.read_port_at(0xfe, 250), 0xBF);
+
+        // Keys still read in the low bits
+        ula.set_ear_input(Some(true));
+        ula.key_pressed(&SpectrumKeycode::A);
+        assert_eq!(ula.read_port(0xfdfe), 0xFE);
+        ula.key_released(&SpectrumKeycode::A);
+
+        // Edges left at the end of the frame are caught up with
+        ula.add_ear_edge(300, false);
+        ula.end_frame();
+        assert_eq!(ula.get_ear_input(), Some(false));
+
+        ula.set_ear_input(None);
+        assert_eq!(ula.read_port(0xfe), 0xFF);
+    }
+
+    #[test]
+    fn test_port_reads_timed() {
+        let memory = Rc::new(RefCell::new(MemoryBuilder::new().finalize()));
+        let ay = Rc::new(RefCell::new(Ay::new()));
+        let ula = Rc::new(RefCell::new(Ula::new(memory.clone())));
+        let interconnect = Interconnect::new(memory, ay, ula.clone());
+
+        ula.borrow_mut().set_ear_input(Some(true));
+        ula.borrow_mut().add_ear_edge(1000, false);
+        assert_eq!(interconnect.read_port(0x7ffe, 999) & 0x40, 0x40);
+        assert_eq!(interconnect.read_port(0x7ffe, 1000) & 0x40, 0x00);
+    }
+
+    #[test]
+    fn test_machine_issue() {
+        let mut rom = vec![0; 16 * 1024];
+        rom[..11].copy_from_slice(&[
+            0x3E, 0x08,         // LD A,08h       ; MIC only
+            0xD3, 0xFE,         // OUT (FEh),A
+            0xDB, 0xFE,         // IN A,(FEh)
+            0x32, 0x00, 0x60,   // LD (6000h),A
+            0x18, 0xFE]);       // JR $
+        let roms = vec![rom.into_boxed_slice()];
+
+        let mut machine = Machine::with_roms(false, Model::Spectrum48K, 48, roms.clone());
+        machine.run_tstates(100);
+        assert_eq!(machine.memory().borrow().read_word(0x6000), 0xBF);
+
+        let mut machine = Machine::with_roms(false, Model::Spectrum48K, 48, roms);
+        machine.set_issue(Issue::Issue2);
+        machine.run_tstates(100);
+        assert_eq!(machine.memory().borrow().read_word(0x6000), 0xFF);
+    }
+}
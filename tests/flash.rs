@@ -0,0 +1,42 @@
+extern crate z80emulib;
+
+#[cfg(test)]
+mod test_flash {
+
+    use z80emulib::peripherals::*;
+
+    use std::rc::Rc;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_flash() {
+        let memory = Rc::new(RefCell::new(MemoryBuilder::new().finalize()));
+        // FLASH, white paper, blue ink, left half set
+        memory.borrow_mut().write_word(0x4000, 0xF0);
+        memory.borrow_mut().write_word(0x5800, 0xB9);
+        // Same without FLASH
+        memory.borrow_mut().write_word(0x4001, 0xF0);
+        memory.borrow_mut().write_word(0x5801, 0x39);
+
+        let mut ula = Ula::new(memory.clone());
+        let mut frames = Vec::new();
+        for _ in 0..33 {
+            ula.end_frame();
+            frames.push(ula.pixels().to_vec());
+        }
+
+        let normal = vec![1, 1, 1, 1, 7, 7, 7, 7];
+        let inverted = vec![7, 7, 7, 7, 1, 1, 1, 1];
+
+        // First 8 pixels of the top left paper cell
+        let row = BORDER_TOP * DISPLAY_WIDTH + BORDER_LEFT;
+        assert_eq!(&frames[0][row..row + 8], &normal[..]);
+        assert_eq!(&frames[15][row..row + 8], &normal[..]);
+        assert_eq!(&frames[16][row..row + 8], &inverted[..]);
+        assert_eq!(&frames[32][row..row + 8], &normal[..]);
+
+        // Only FLASH cells change
+        assert_eq!(&frames[16][row + 8..row + 16], &normal[..]);
+        assert_eq!(ula.get_frame_count(), 33);
+    }
+}
@@ -0,0 +1,97 @@
+extern crate z80emulib;
+
+#[cfg(test)]
+mod test_floating_bus {
+
+    use z80emulib::cpu::*;
+    use z80emulib::peripherals::*;
+    use z80emulib::interconnect::*;
+
+    use std::rc::Rc;
+    use std::cell::RefCell;
+
+    fn machine(timing: UlaTiming, program: &[u8]) -> Cpu {
+        let mut rom = vec![0; 16 * 1024];
+        rom[..program.len()].copy_from_slice(program);
+        let memory = Rc::new(RefCell::new(MemoryBuilder::new()
+                        .rom0(rom.into_boxed_slice())
+                        .finalize()));
+
+        // Distinct bytes at the start of the first two screen lines
+        for x in 0..4 {
+            memory.borrow_mut().write_word(0x4000 + x, 0x10 + x as u8);
+            memory.borrow_mut().write_word(0x4100 + x, 0x20 + x as u8);
+            memory.borrow_mut().write_word(0x5800 + x, 0x30 + x as u8);
+        }
+
+        let ay = Rc::new(RefCell::new(Ay::new()));
+        let ula = Rc::new(RefCell::new(Ula::new(memory.clone())));
+        let mut interconnect = Interconnect::new(memory.clone(), ay, ula);
+        interconnect.set_ula_timing(timing);
+        Cpu::new(interconnect)
+    }
+
+    #[test]
+    fn test_fetch_sequence() {
+        let cpu = machine(SPECTRUM_48K, &[]);
+        let bus: Vec<u8> = (14336..14348)
+            .map(|t| cpu.interconnect().read_port(0x00FF, t))
+            .collect();
+        assert_eq!(bus, vec![0xFF, 0xFF,
+                             0x10, 0x30, 0x11, 0x31, 0xFF, 0xFF, 0xFF, 0xFF,
+                             0x12, 0x32]);
+
+        // Second screen line, then the right border
+        assert_eq!(cpu.interconnect().read_port(0x00FF, 14338 + 224), 0x20);
+        assert_eq!(cpu.interconnect().read_port(0x00FF, 14338 + 224 + 3), 0x31);
+        assert_eq!(cpu.interconnect().read_port(0x00FF, 14338 + 128), 0xFF);
+    }
+
+    #[test]
+    fn test_models() {
+        let cpu = machine(SPECTRUM_128K, &[]);
+        assert_eq!(cpu.interconnect().read_port(0x00FF, 14363), 0xFF);
+        assert_eq!(cpu.interconnect().read_port(0x00FF, 14364), 0x10);
+        assert_eq!(cpu.interconnect().read_port(0x00FF, 14364 + 228 + 2), 0x21);
+
+        // Idle bus on the Pentagon
+        let cpu = machine(PENTAGON, &[]);
+        assert_eq!(cpu.interconnect().read_port(0x00FF, 14338), 0xFF);
+    }
+
+    #[test]
+    fn test_in_instruction() {
+        // IN A,(FFh) samples the bus 8 T states into the instruction
+        let mut cpu = machine(SPECTRUM_48K, &[
+            0xDB, 0xFF,         // IN A,(FFh)
+            0xDB, 0xFF]);       // IN A,(FFh)
+        cpu.tcycles = 14338 - 8;
+        cpu.run_instruction();
+        assert_eq!(cpu.read_reg8(Reg8::A), 0x10);
+        assert_eq!(cpu.tcycles, 14338 + 3);
+
+        // The next one lands on the attribute of the fourth cell at 14349
+        cpu.run_instruction();
+        assert_eq!(cpu.read_reg8(Reg8::A), 0x33);
+    }
+
+    #[test]
+    fn test_paging_port() {
+        // 0x7FFD is write-only, reading it gets whatever is on the bus
+        let cpu = machine(SPECTRUM_128K, &[]);
+        assert_eq!(cpu.interconnect().read_port(0x7FFD, 14364), 0x10);
+        assert_eq!(cpu.interconnect().read_port(0x7FFD, 14365), 0x30);
+        assert_eq!(cpu.interconnect().read_port(0x7FFD, 14363), 0xFF);
+    }
+
+    #[test]
+    fn test_turbo_clock() {
+        // At twice the ULA clock the fetches come every other CPU T state
+        let mut cpu = machine(SPECTRUM_128K, &[]);
+        cpu.interconnect_mut().set_cpu_hz(Some(7_093_800));
+        assert_eq!(cpu.interconnect().read_port(0x00FF, 2 * 14364), 0x10);
+        assert_eq!(cpu.interconnect().read_port(0x00FF, 2 * 14365), 0x30);
+        assert_eq!(cpu.interconnect().read_port(0x00FF, 2 * (14364 + 228 + 2)), 0x21);
+        assert_eq!(cpu.interconnect().read_port(0x00FF, 14364), 0xFF);
+    }
+}
@@ -0,0 +1,70 @@
+extern crate z80emulib;
+
+#[cfg(test)]
+mod test_framebuffer {
+
+    use z80emulib::peripherals::*;
+    use z80emulib::framebuffer::*;
+
+    use std::rc::Rc;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_display() {
+        let memory = Rc::new(RefCell::new(MemoryBuilder::new().finalize()));
+        // Bright red ink on blue paper, leftmost pixel set
+        memory.borrow_mut().write_word(0x4000, 0x80);
+        memory.borrow_mut().write_word(0x5800, 0x4A);
+
+        let mut ula = Ula::new(memory.clone());
+        ula.end_frame();
+
+        let mut framebuffer = Framebuffer::new(DISPLAY_WIDTH, DISPLAY_HEIGHT);
+        ula.display(&mut framebuffer);
+
+        assert_eq!(framebuffer.pitch(), DISPLAY_WIDTH * 3);
+        assert_eq!(framebuffer.data().len(), DISPLAY_WIDTH * DISPLAY_HEIGHT * 3);
+        assert_eq!(framebuffer.get_pixel(0, 0), (192, 192, 192));
+        assert_eq!(framebuffer.get_pixel(BORDER_LEFT, BORDER_TOP), (255, 0, 0));
+        assert_eq!(framebuffer.get_pixel(BORDER_LEFT + 1, BORDER_TOP), (0, 0, 255));
+    }
+
+    fn checkerboard() -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(3, 2);
+        for y in 0..2 {
+            for x in 0..3 {
+                let colour = if (x + y) % 2 == 0 { (255, 255, 255) } else { (0, 0, 0) };
+                framebuffer.set_pixel(x, y, colour);
+            }
+        }
+        framebuffer
+    }
+
+    #[test]
+    fn test_ppm() {
+        let mut ppm = Vec::new();
+        checkerboard().write_ppm(&mut ppm).unwrap();
+        assert_eq!(&ppm[..11], b"P6\n3 2\n255\n");
+        assert_eq!(ppm.len(), 11 + 3 * 2 * 3);
+        assert_eq!(&ppm[11..17], &[255, 255, 255, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_png() {
+        let mut png = Vec::new();
+        checkerboard().write_png(&mut png).unwrap();
+
+        assert_eq!(&png[..8], &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]);
+        // IHDR: 3x2, 8 bit RGB
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..29], &[0, 0, 0, 3, 0, 0, 0, 2, 8, 2, 0, 0, 0]);
+        // IDAT holds the zlib header, one stored block and the Adler-32
+        let idat_len = 2 + 5 + 2 * (1 + 9) + 4;
+        assert_eq!(&png[33..37], &[0, 0, 0, idat_len as u8]);
+        assert_eq!(&png[37..41], b"IDAT");
+        assert_eq!(&png[41..48], &[0x78, 0x01, 0x01, 20, 0, !20, 0xFF]);
+        // IEND and its well known CRC
+        assert_eq!(&png[png.len() - 12..], &[0, 0, 0, 0, b'I', b'E', b'N', b'D',
+                                             0xAE, 0x42, 0x60, 0x82]);
+    }
+}
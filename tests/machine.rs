@@ -0,0 +1,117 @@
+extern crate z80emulib;
+
+#[cfg(test)]
+mod test_machine {
+
+    use z80emulib::machine::*;
+    use z80emulib::cpu::*;
+    use z80emulib::peripherals::*;
+
+    fn test_machine(model: Model, ram_size: usize) -> Machine {
+        let mut rom = vec![0; 16 * 1024];
+        rom[..17].copy_from_slice(&[
+            0xF3,               // DI
+            0x31, 0x00, 0x80,   // LD SP,8000h
+            0x3E, 0x02,         // LD A,2
+            0xD3, 0xFE,         // OUT (FEh),A    ; red border
+            0x3E, 0xFE,         // LD A,FEh       ; CAPS SHIFT to V
+            0xDB, 0xFE,         // IN A,(FEh)
+            0x32, 0x00, 0x60,   // LD (6000h),A
+            0x18, 0xF7]);       // JR -9
+        Machine::with_roms(false, model, ram_size, vec![rom.into_boxed_slice()])
+    }
+
+    #[test]
+    fn test_run_frame() {
+        let mut machine = test_machine(Model::Spectrum48K, 48);
+        machine.run_frame();
+        assert_eq!(machine.framebuffer().get_pixel(0, 0), (192, 0, 0));
+        // The speaker is never moved
+        assert_eq!(machine.audio_samples(), vec![0; 2 * 880]);
+    }
+
+    #[test]
+    fn test_keyboard() {
+        let mut machine = test_machine(Model::Spectrum16K, 16);
+        // EAR was last written low, which an issue 3 reads back in bit 6
+        machine.run_tstates(100);
+        assert_eq!(machine.memory().borrow().read_word(0x6000), 0xBF);
+
+        machine.press_key(SpectrumKeycode::Z);
+        machine.run_tstates(100);
+        assert_eq!(machine.memory().borrow().read_word(0x6000), 0xBD);
+
+        machine.release_key(SpectrumKeycode::Z);
+        machine.run_tstates(100);
+        assert_eq!(machine.memory().borrow().read_word(0x6000), 0xBF);
+    }
+
+    #[test]
+    fn test_run_tstates() {
+        let mut machine = test_machine(Model::Spectrum48K, 48);
+        machine.run_tstates(69888 * 2 + 10);
+        // Frames wrap around, and the ULA's interrupts are disabled
+        let tcycles = machine.cpu().borrow().tcycles;
+        assert!(tcycles >= 10 && tcycles < 40, "{}", tcycles);
+    }
+
+    #[test]
+    fn test_load_snapshot() {
+        // Version 1 .z80: PC 8000h, SP 9000h, HALT at 8000h
+        let mut snapshot = vec![0; 30 + 48 * 1024];
+        snapshot[6] = 0x00;
+        snapshot[7] = 0x80;
+        snapshot[8] = 0x00;
+        snapshot[9] = 0x90;
+        snapshot[30 + 0x4000] = 0x76;
+
+        let mut machine = test_machine(Model::Spectrum48K, 48);
+        machine.load_snapshot(&snapshot).unwrap();
+        machine.run_tstates(100);
+        assert_eq!(machine.memory().borrow().read_word(0x8000), 0x76);
+        assert!(machine.cpu().borrow().is_halted());
+        assert_eq!(machine.cpu().borrow().read_reg16(Reg16::SP), 0x9000);
+
+        // Memory at 8000h doesn't exist on a 16K
+        let mut machine = test_machine(Model::Spectrum16K, 16);
+        assert!(machine.load_snapshot(&snapshot).is_err());
+    }
+
+    #[test]
+    fn test_load_snapshot_ulaplus() {
+        let mut snapshot = vec![0; 30 + 48 * 1024];
+        snapshot[7] = 0x80;
+        snapshot[30 + 0x4000] = 0x76;
+
+        // Palette mode, with green for the paper of the red border
+        let mut ulaplus = UlaPlus::new();
+        ulaplus.set_entry(0x0A, 0xE0);
+        ulaplus.write_port(ULAPLUS_REGISTER_PORT, 0x40);
+        ulaplus.write_port(ULAPLUS_DATA_PORT, 0x01);
+
+        let mut machine = test_machine(Model::Spectrum48K, 48);
+        assert!(machine.ulaplus().is_none());
+        machine.set_ulaplus(ulaplus);
+        machine.run_frame();
+        assert_eq!(machine.framebuffer().get_pixel(0, 0), (0, 255, 0));
+
+        // A .z80 doesn't keep the ULAplus state, the program it holds gets
+        // the standard colours
+        machine.load_snapshot(&snapshot).unwrap();
+        let restored = machine.ulaplus().unwrap();
+        assert!(!restored.palette_mode());
+        assert_eq!(restored.get_entry(0x0A), 0);
+        machine.run_frame();
+        assert_eq!(machine.framebuffer().get_pixel(0, 0), (192, 0, 0));
+    }
+
+    #[test]
+    fn test_load_bytes() {
+        let mut machine = test_machine(Model::Spectrum48K, 48);
+        machine.load_bytes(0xFFFE, &[1, 2, 3]);
+        let memory = machine.memory();
+        assert_eq!(memory.borrow().read_word(0xFFFF), 2);
+        // Writes wrap around into ROM, which stays as it is
+        assert_eq!(memory.borrow().read_word(0x0000), 0xF3);
+    }
+}
@@ -0,0 +1,61 @@
+extern crate z80emulib;
+
+#[cfg(test)]
+mod test_memory {
+
+    use z80emulib::peripherals::*;
+    use z80emulib::machine::*;
+
+    #[test]
+    fn test_16k_memory_map() {
+        let mut memory = MemoryBuilder::new()
+            .ram_size(16)
+            .finalize();
+
+        memory.write_word(0x7FFF, 0x12);
+        memory.write_word(0x8000, 0x34);
+        memory.write_word(0xFFFF, 0x56);
+        assert_eq!(memory.read_word(0x7FFF), 0x12);
+        assert_eq!(memory.read_word(0x8000), 0xFF);
+        assert_eq!(memory.read_word(0xFFFF), 0xFF);
+
+        // No 128K paging on a 48K memory map
+        memory.write_port(0x7FFD, 0x17);
+        assert_eq!(memory.get_c000_bank(), 0);
+        assert_eq!(memory.get_0000_bank(), 0);
+    }
+
+    #[test]
+    fn test_ram_size() {
+        let mut memory = MemoryBuilder::new()
+            .ram_size(32)
+            .finalize();
+
+        memory.write_word(0xBFFF, 0x12);
+        memory.write_word(0xC000, 0x34);
+        assert_eq!(memory.read_word(0xBFFF), 0x12);
+        assert_eq!(memory.read_word(0xC000), 0xFF);
+
+        assert!(Model::Spectrum48K.supports_ram_size(32));
+        assert!(!Model::Spectrum48K.supports_ram_size(64));
+        assert!(!Model::Spectrum16K.supports_ram_size(48));
+    }
+
+    #[test]
+    fn test_snapshot_banks() {
+        let mut data = vec![0; 48 * 1024];
+        data[0x3FFF] = 0xAA;
+        let banks = snapshot_banks(16, data.clone()).unwrap();
+        assert_eq!(banks[0][0x3FFF], 0xAA);
+
+        // Anything above 0x8000 needs more than 16K
+        data[0x4000] = 0xBB;
+        assert!(snapshot_banks(16, data.clone()).is_err());
+        let banks = snapshot_banks(48, data).unwrap();
+        assert_eq!(banks[1][0], 0xBB);
+
+        // Short images are padded out
+        let banks = snapshot_banks(48, vec![0xCC; 100]).unwrap();
+        assert_eq!(banks.iter().map(|bank| bank.len()).sum::<usize>(), 48 * 1024);
+    }
+}
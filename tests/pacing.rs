@@ -0,0 +1,136 @@
+extern crate z80emulib;
+
+#[cfg(test)]
+mod test_pacing {
+
+    use z80emulib::pacing::*;
+
+    use std::time::Duration;
+
+    // Time only moves when the pacer sleeps, or when a test says so
+    struct FakeClock {
+        now: Duration,
+    }
+
+    impl Clock for FakeClock {
+        fn now(&self) -> Duration {
+            self.now
+        }
+
+        fn sleep(&mut self, duration: Duration) {
+            self.now += duration;
+        }
+    }
+
+    fn pacer() -> FramePacer<FakeClock> {
+        FramePacer::new(FakeClock { now: Duration::new(0, 0) }, Duration::from_millis(20))
+    }
+
+    fn run_frames(pacer: &mut FramePacer<FakeClock>, frames: u32) -> u32 {
+        let mut run = 0;
+        for _ in 0..frames {
+            if pacer.frame_due() {
+                pacer.end_frame();
+                run += 1;
+            }
+        }
+        run
+    }
+
+    #[test]
+    fn test_real_speed() {
+        let mut pacer = pacer();
+        run_frames(&mut pacer, 50);
+        assert_eq!(pacer.clock().now, Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn test_speed_percentage() {
+        let mut pacer = pacer();
+        pacer.set_speed(200);
+        run_frames(&mut pacer, 50);
+        assert_eq!(pacer.clock().now, Duration::from_millis(500));
+
+        let mut pacer = self::pacer();
+        pacer.set_speed(50);
+        run_frames(&mut pacer, 50);
+        assert_eq!(pacer.clock().now, Duration::from_millis(2000));
+    }
+
+    #[test]
+    fn test_slow_frame_made_up() {
+        let mut pacer = pacer();
+        pacer.end_frame();
+        // The next frame takes 30ms to emulate, the one after only gets 10ms
+        pacer.clock_mut().now += Duration::from_millis(30);
+        pacer.end_frame();
+        assert_eq!(pacer.clock().now, Duration::from_millis(50));
+        pacer.end_frame();
+        assert_eq!(pacer.clock().now, Duration::from_millis(60));
+    }
+
+    #[test]
+    fn test_fast_forward() {
+        let mut pacer = pacer();
+        pacer.set_fast_forward(true);
+        run_frames(&mut pacer, 50);
+        assert_eq!(pacer.clock().now, Duration::new(0, 0));
+
+        pacer.set_fast_forward(false);
+        run_frames(&mut pacer, 50);
+        assert_eq!(pacer.clock().now, Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn test_pause_and_advance() {
+        let mut pacer = pacer();
+        pacer.toggle_pause();
+        assert!(pacer.is_paused());
+        assert_eq!(run_frames(&mut pacer, 10), 0);
+
+        pacer.advance_frame();
+        pacer.advance_frame();
+        assert_eq!(run_frames(&mut pacer, 10), 2);
+
+        pacer.toggle_pause();
+        assert!(!pacer.is_paused());
+        assert_eq!(run_frames(&mut pacer, 10), 10);
+    }
+
+    #[test]
+    fn test_audio_feeder() {
+        let mut feeder = AudioFeeder::new(100, 1);
+
+        // Steady state, everything goes through
+        let samples: Vec<i16> = (0..50).collect();
+        assert_eq!(feeder.feed(100, &samples), samples);
+        assert!(feeder.is_ahead(150));
+        assert!(!feeder.is_ahead(100));
+
+        // Running dry holds the last sample first
+        let out = feeder.feed(0, &[7, 8]);
+        assert_eq!(out.len(), 52);
+        assert!(out[..50].iter().all(|&s| s == 49));
+        assert_eq!(&out[50..], &[7, 8]);
+
+        // Overfilling drops samples evenly
+        let samples: Vec<i16> = (0..100).collect();
+        let out = feeder.feed(150, &samples);
+        assert_eq!(out, (0..50).map(|i| i * 2).collect::<Vec<i16>>());
+        assert!(feeder.feed(200, &samples).is_empty());
+    }
+
+    #[test]
+    fn test_stereo_audio_feeder() {
+        let mut feeder = AudioFeeder::new(10, 2);
+        let samples: Vec<i16> = (0..40).collect();
+
+        // Frames are dropped whole, left and right stay together
+        let out = feeder.feed(5, &samples);
+        assert_eq!(out.len(), 30);
+        assert!(out.chunks(2).all(|frame| frame[0] % 2 == 0 && frame[1] == frame[0] + 1));
+
+        let out = feeder.feed(0, &[]);
+        assert_eq!(out, vec![36, 37, 36, 37, 36, 37, 36, 37, 36, 37]);
+    }
+}
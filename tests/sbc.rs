@@ -0,0 +1,87 @@
+extern crate z80emulib;
+
+#[cfg(test)]
+mod test_sbc {
+
+    use z80emulib::peripherals::*;
+    use z80emulib::sbc::*;
+
+    use std::io;
+    use std::io::Write;
+    use std::sync::mpsc::channel;
+
+    use std::rc::Rc;
+    use std::cell::RefCell;
+
+    #[derive(Clone)]
+    struct Terminal(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Terminal {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // Echoes every received character from the RST 38 handler
+    fn echo_rom() -> Box<[u8]> {
+        let mut rom = vec![
+            0x31, 0x00, 0xFF,   // LD SP,FF00h
+            0x3E, 0x96,         // LD A,96h      ; RX interrupt, 8N1, /64
+            0xD3, 0x80,         // OUT (80h),A
+            0xED, 0x56,         // IM 1
+            0xFB,               // EI
+            0x76,               // HALT
+            0x18, 0xFD];        // JR -3
+        rom.resize(0x38, 0x00);
+        rom.extend_from_slice(&[
+            0xF5,               // PUSH AF
+            0xDB, 0x81,         // IN A,(81h)
+            0xD3, 0x81,         // OUT (81h),A
+            0xF1,               // POP AF
+            0xFB,               // EI
+            0xED, 0x4D]);       // RETI
+        rom.into_boxed_slice()
+    }
+
+    #[test]
+    fn test_acia_echo() {
+        let (sender, receiver) = channel();
+        let terminal = Terminal(Rc::new(RefCell::new(Vec::new())));
+
+        let sbc = SbcBuilder::new(echo_rom())
+            .acia(Acia::new(receiver, Box::new(terminal.clone())), 0x80)
+            .finalize();
+
+        for _ in 0..100 { sbc.step(); }
+        assert!(terminal.0.borrow().is_empty());
+
+        for byte in b"RC2014" {
+            sender.send(*byte).unwrap();
+        }
+        for _ in 0..1000 { sbc.step(); }
+
+        assert_eq!(&terminal.0.borrow()[..], b"RC2014");
+    }
+
+    #[test]
+    fn test_memory_map() {
+        let sbc = SbcBuilder::new(echo_rom())
+            .acia(Acia::new(channel().1, Box::new(io::sink())), 0x80)
+            .finalize();
+
+        let memory = sbc.memory();
+        memory.borrow_mut().write_word(0x0000, 0x00);
+        memory.borrow_mut().write_word(0x4000, 0x12);
+        memory.borrow_mut().write_word(0x8000, 0x34);
+
+        assert_eq!(memory.borrow().read_word(0x0000), 0x31);
+        assert_eq!(memory.borrow().read_word(0x2000), 0xFF);
+        assert_eq!(memory.borrow().read_word(0x4000), 0xFF);
+        assert_eq!(memory.borrow().read_word(0x8000), 0x34);
+    }
+}
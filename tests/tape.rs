@@ -0,0 +1,215 @@
+extern crate z80emulib;
+
+#[cfg(test)]
+mod test_tape {
+
+    use z80emulib::tape::*;
+    use z80emulib::machine::*;
+    use z80emulib::cpu::*;
+
+    // A block as the ROM saves it, flag and checksum around the data
+    fn rom_block(flag: u8, data: &[u8]) -> Vec<u8> {
+        let mut block = vec![flag];
+        block.extend_from_slice(data);
+        let checksum = block.iter().fold(0, |acc, &byte| acc ^ byte);
+        block.push(checksum);
+        block
+    }
+
+    fn tap(blocks: &[Vec<u8>]) -> Vec<u8> {
+        let mut tap = Vec::new();
+        for block in blocks {
+            tap.push(block.len() as u8);
+            tap.push((block.len() >> 8) as u8);
+            tap.extend_from_slice(block);
+        }
+        tap
+    }
+
+    #[test]
+    fn test_parse_tap() {
+        let mut header = vec![0x03];
+        header.extend_from_slice(b"screen    ");
+        header.extend_from_slice(&[0x00, 0x1B, 0x00, 0x40, 0x00, 0x80]);
+        let blocks = vec![rom_block(0x00, &header), rom_block(0xFF, &[1, 2, 3])];
+        let tape = parse_tap(&tap(&blocks)).unwrap();
+
+        assert_eq!(tape.len(), 2);
+        match tape.blocks()[0] {
+            Block::Data(ref block) => {
+                assert_eq!(block.data, blocks[0]);
+                assert_eq!(block.pilot_pulses, HEADER_PILOT_PULSES);
+                assert_eq!(block.pause_ms, 1000);
+            }
+            ref block => panic!("{:?}", block),
+        }
+        match tape.blocks()[1] {
+            Block::Data(ref block) => assert_eq!(block.pilot_pulses, DATA_PILOT_PULSES),
+            ref block => panic!("{:?}", block),
+        }
+        assert_eq!(tape.blocks()[0].describe(), "Bytes: screen");
+        assert_eq!(tape.blocks()[1].describe(), "Data, 5 bytes");
+
+        // Cut short in the middle of a block
+        let mut truncated = tap(&blocks);
+        truncated.pop();
+        assert!(parse_tap(&truncated).is_err());
+        assert!(parse_tap(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_pulses() {
+        let block = DataBlock::standard(vec![0xA0, 0x00], 0);
+        let pulses = block.pulses();
+        assert_eq!(pulses.len(), 3223 + 2 + 32);
+        assert_eq!(&pulses[3222..3229], &[2168, 667, 735, 1710, 1710, 855, 855]);
+        assert_eq!(pulses[3225 + 4], 1710);
+    }
+
+    fn short_block(pause_ms: u32) -> Block {
+        Block::Data(DataBlock {
+            pilot_pulse: 100,
+            pilot_pulses: 2,
+            sync1_pulse: 10,
+            sync2_pulse: 20,
+            zero_pulse: 30,
+            one_pulse: 60,
+            last_byte_bits: 2,
+            pause_ms,
+            data: vec![0x40],
+        })
+    }
+
+    #[test]
+    fn test_player() {
+        let mut player = TapePlayer::new(Tape::new(vec![short_block(1), short_block(0)]));
+        assert!(player.run(100).is_empty());
+
+        player.play();
+        let edges = player.run(500);
+        // Pilot, sync, then a 0 and a 1
+        assert_eq!(edges, vec![(0, true), (100, false), (200, true), (210, false),
+                               (230, true), (260, false), (290, true), (350, false)]);
+        assert_eq!(player.block(), 0);
+
+        // The pause is low, so the next block starts with a rising edge
+        let edges = player.run(3500);
+        assert_eq!(edges, vec![(3410, true)]);
+        assert_eq!(player.block(), 1);
+
+        player.run(1000);
+        assert!(!player.is_playing());
+        assert!(player.is_at_end());
+
+        player.rewind();
+        assert_eq!(player.block(), 0);
+        assert!(player.take_data_block().is_some());
+        assert_eq!(player.block(), 1);
+    }
+
+    #[test]
+    fn test_player_clock() {
+        let mut player = TapePlayer::new(Tape::new(vec![short_block(0)]));
+        player.set_clock(7_000_000);
+        player.play();
+        let edges = player.run(1000);
+        assert_eq!(&edges[..4], &[(0, true), (200, false), (400, true), (420, false)]);
+    }
+
+    fn loader_machine(rom: Vec<u8>, blocks: &[Vec<u8>]) -> Machine {
+        let mut machine = Machine::with_roms(false, Model::Spectrum48K, 48, vec![rom.into_boxed_slice()]);
+        machine.insert_tape(parse_tap(&tap(blocks)).unwrap());
+        machine
+    }
+
+    fn fast_load_rom() -> Vec<u8> {
+        let mut rom = vec![0; 16 * 1024];
+        rom[..17].copy_from_slice(&[
+            0x31, 0x00, 0x80,   // LD SP,8000h
+            0xDD, 0x21, 0x00, 0x90, // LD IX,9000h
+            0x11, 0x03, 0x00,   // LD DE,3
+            0x3E, 0xFF,         // LD A,FFh
+            0x37,               // SCF
+            0xCD, 0x56, 0x05,   // CALL LD-BYTES
+            0x76]);             // HALT
+        // Never gets to run
+        rom[0x0556..0x0558].copy_from_slice(&[0x18, 0xFE]);
+        rom
+    }
+
+    #[test]
+    fn test_fast_load() {
+        let mut machine = loader_machine(fast_load_rom(), &[rom_block(0xFF, &[1, 2, 3])]);
+        assert_eq!(machine.tape_position(), Some((0, 1)));
+        machine.run_tstates(200);
+
+        let cpu = machine.cpu();
+        assert!(cpu.borrow().is_halted());
+        assert!(cpu.borrow().get_flag(CARRY_FLAG));
+        assert_eq!(cpu.borrow().read_reg16(Reg16::IX), 0x9003);
+        assert_eq!(cpu.borrow().read_reg16(Reg16::DE), 0);
+        assert_eq!(cpu.borrow().read_reg16(Reg16::SP), 0x8000);
+        let memory = machine.memory();
+        assert_eq!((0..3).map(|i| memory.borrow().read_word(0x9000 + i)).collect::<Vec<u8>>(), vec![1, 2, 3]);
+        assert_eq!(machine.tape_position(), Some((1, 1)));
+    }
+
+    #[test]
+    fn test_fast_load_errors() {
+        // Wrong flag
+        let mut machine = loader_machine(fast_load_rom(), &[rom_block(0x00, &[1, 2, 3])]);
+        machine.run_tstates(200);
+        assert!(machine.cpu().borrow().is_halted());
+        assert!(!machine.cpu().borrow().get_flag(CARRY_FLAG));
+
+        // Bad checksum, the bytes are loaded all the same
+        let mut block = rom_block(0xFF, &[1, 2, 3]);
+        block[4] ^= 0x01;
+        let mut machine = loader_machine(fast_load_rom(), &[block]);
+        machine.run_tstates(200);
+        assert!(!machine.cpu().borrow().get_flag(CARRY_FLAG));
+        assert_eq!(machine.memory().borrow().read_word(0x9002), 3);
+
+        // Block too short, the checksum is taken for data
+        let mut machine = loader_machine(fast_load_rom(), &[rom_block(0xFF, &[1])]);
+        machine.run_tstates(200);
+        assert!(!machine.cpu().borrow().get_flag(CARRY_FLAG));
+        assert_eq!(machine.cpu().borrow().read_reg16(Reg16::DE), 1);
+    }
+
+    #[test]
+    fn test_real_time() {
+        let mut rom = vec![0; 16 * 1024];
+        rom[..9].copy_from_slice(&[
+            0xF3,               // DI
+            0x21, 0x00, 0x00,   // LD HL,0
+            0x0E, 0x00,         // LD C,0
+            0xC3, 0x56, 0x05]); // JP LD-BYTES
+        // Count the changes of EAR
+        rom[0x0556..0x0564].copy_from_slice(&[
+            0xDB, 0xFE,         // IN A,(FEh)
+            0xE6, 0x40,         // AND 40h
+            0xB9,               // CP C
+            0x28, 0xF9,         // JR Z,-7
+            0x4F,               // LD C,A
+            0x23,               // INC HL
+            0x22, 0x00, 0x60,   // LD (6000h),HL
+            0x18, 0xF2]);       // JR -14
+        let mut machine = loader_machine(rom, &[rom_block(0xFF, &[1, 2, 3])]);
+        machine.set_tape_fast_load(false);
+
+        machine.run_frame();
+        assert!(machine.is_tape_playing());
+
+        // Two seconds of tone, sync and 5 bytes, then a second of silence
+        for _ in 0..160 {
+            machine.run_frame();
+        }
+        let memory = machine.memory();
+        let edges = memory.borrow().read_word(0x6000) as u16 | (memory.borrow().read_word(0x6001) as u16) << 8;
+        // An odd number of pulses leaves the signal high, until the pause
+        assert_eq!(edges, 3223 + 2 + 5 * 16 + 1);
+        assert!(!machine.is_tape_playing());
+        assert_eq!(machine.tape_position(), Some((1, 1)));
+    }
+}
@@ -0,0 +1,83 @@
+extern crate z80emulib;
+
+#[cfg(test)]
+mod test_turbosound {
+
+    use z80emulib::peripherals::*;
+    use z80emulib::interconnect::*;
+    use z80emulib::mixer::*;
+
+    use std::rc::Rc;
+    use std::cell::RefCell;
+
+    fn write(interconnect: &Interconnect, register: u8, val: u8) {
+        interconnect.write_port(AY_SELECT_PORT, register, 0);
+        interconnect.write_port(AY_DATA_PORT, val, 0);
+    }
+
+    fn read(interconnect: &Interconnect, register: u8) -> u8 {
+        interconnect.write_port(AY_SELECT_PORT, register, 0);
+        interconnect.read_port(AY_SELECT_PORT, 0)
+    }
+
+    #[test]
+    fn test_chip_select() {
+        let memory = Rc::new(RefCell::new(MemoryBuilder::new().finalize()));
+        let ay0 = Rc::new(RefCell::new(Ay::new()));
+        let ay1 = Rc::new(RefCell::new(Ay::new()));
+        let ula = Rc::new(RefCell::new(Ula::new(memory.clone())));
+        let mut interconnect = Interconnect::new(memory.clone(), ay0.clone(), ula);
+
+        // A single AY ignores the select values
+        interconnect.write_port(AY_SELECT_PORT, 0xFE, 0);
+        write(&interconnect, 8, 0x0A);
+        assert_eq!(ay0.borrow().get_register(8), 0x0A);
+
+        interconnect.add_ay(ay1.clone());
+        interconnect.write_port(AY_SELECT_PORT, 0xFE, 0);
+        write(&interconnect, 8, 0x05);
+        assert_eq!(read(&interconnect, 8), 0x05);
+        assert_eq!(ay0.borrow().get_register(8), 0x0A);
+        assert_eq!(ay1.borrow().get_register(8), 0x05);
+
+        interconnect.write_port(AY_SELECT_PORT, 0xFF, 0);
+        assert_eq!(read(&interconnect, 8), 0x0A);
+    }
+
+    #[test]
+    fn test_stereo_modes() {
+        let beeper = vec![0; 1];
+        let ay = vec![vec![[0x8000, 0x4000, 0x2000]]];
+
+        let mut mixer = Mixer::new();
+        // Levels add up and are scaled down by 8 for one AY. Every source in the middle at full volume
+        assert_eq!(mixer.mix(&beeper, &ay, &[]), vec![7168, 7168]);
+
+        mixer.set_stereo_mode(StereoMode::Abc, 1.0);
+        assert_eq!(mixer.mix(&beeper, &ay, &[]), vec![6144, 3072]);
+
+        mixer.set_stereo_mode(StereoMode::Acb, 1.0);
+        assert_eq!(mixer.mix(&beeper, &ay, &[]), vec![5120, 3072]);
+
+        // Half way to the sides
+        mixer.set_stereo_mode(StereoMode::Abc, 0.5);
+        assert_eq!(mixer.mix(&beeper, &ay, &[]), vec![6656, 5120]);
+
+        mixer.set_panning([1.0, -1.0, 0.0]);
+        assert_eq!(mixer.mix(&beeper, &ay, &[]), vec![3072, 5120]);
+    }
+
+    #[test]
+    fn test_two_chips_mixed() {
+        let mixer = Mixer::new();
+        let beeper = vec![0xFFFF; 2];
+        let ays = vec![vec![[0xFFFF; 3]; 2], vec![[0xFFFF; 3]]];
+        // Full scale with everything on, the second chip came up short and
+        // holds its level
+        assert_eq!(mixer.mix(&beeper, &ays, &[]), vec![32767, 32767, 32767, 32767]);
+
+        // So does a DAC
+        let ays = vec![vec![[0; 3]; 2]];
+        assert_eq!(mixer.mix(&beeper, &ays, &[vec![0xFFFF]]), vec![13107, 13107, 13107, 13107]);
+    }
+}
@@ -0,0 +1,207 @@
+extern crate z80emulib;
+
+#[cfg(test)]
+mod test_tzx {
+
+    use z80emulib::tape::*;
+
+    fn tzx(blocks: &[&[u8]]) -> Vec<u8> {
+        let mut tzx = b"ZXTape!\x1A\x01\x14".to_vec();
+        for block in blocks {
+            tzx.extend_from_slice(block);
+        }
+        tzx
+    }
+
+    #[test]
+    fn test_parse_blocks() {
+        let tape = parse_tzx(&tzx(&[
+            // Standard speed, 500ms pause
+            &[0x10, 0xF4, 0x01, 0x03, 0x00, 0xFF, 0xAA, 0x55],
+            // Turbo speed
+            &[0x11, 0x78, 0x08, 0x9B, 0x02, 0xDF, 0x02, 0x57, 0x03, 0xAE, 0x06,
+              0x7F, 0x1F, 0x06, 0x00, 0x00, 0x02, 0x00, 0x00, 0x12, 0x34],
+            // Pure tone, pulse sequence
+            &[0x12, 0x64, 0x00, 0x0A, 0x00],
+            &[0x13, 0x02, 0x0A, 0x00, 0x14, 0x00],
+            // Pure data
+            &[0x14, 0x57, 0x03, 0xAE, 0x06, 0x08, 0xE8, 0x03, 0x01, 0x00, 0x00, 0xF0],
+            // Direct recording
+            &[0x15, 0x4F, 0x00, 0x00, 0x00, 0x04, 0x01, 0x00, 0x00, 0xC3],
+            // Pause, group, jump and loop
+            &[0x20, 0x00, 0x00],
+            &[0x21, 0x04, b'D', b'e', b'm', b'o'],
+            &[0x22],
+            &[0x23, 0xFE, 0xFF],
+            &[0x24, 0x05, 0x00],
+            &[0x25],
+            // Stop on 48K, set signal level
+            &[0x2A, 0x00, 0x00, 0x00, 0x00],
+            &[0x2B, 0x01, 0x00, 0x00, 0x00, 0x01],
+            // Text, archive info and glue
+            &[0x30, 0x03, b'H', b'i', b'!'],
+            &[0x32, 0x03, 0x00, 0x01, 0x00, 0x00],
+            &[0x5A, b'X', b'T', b'a', b'p', b'e', b'!', 0x1A, 0x01, 0x14],
+        ])).unwrap();
+
+        assert_eq!(tape.blocks(), &[
+            Block::Data(DataBlock::standard(vec![0xFF, 0xAA, 0x55], 500)),
+            Block::Data(DataBlock {
+                pilot_pulse: 2168,
+                pilot_pulses: 8063,
+                sync1_pulse: 667,
+                sync2_pulse: 735,
+                zero_pulse: 855,
+                one_pulse: 1710,
+                last_byte_bits: 6,
+                pause_ms: 0,
+                data: vec![0x12, 0x34],
+            }),
+            Block::PureTone { pulse: 100, count: 10 },
+            Block::Pulses(vec![10, 20]),
+            Block::Data(DataBlock {
+                pilot_pulse: 0,
+                pilot_pulses: 0,
+                sync1_pulse: 0,
+                sync2_pulse: 0,
+                zero_pulse: 855,
+                one_pulse: 1710,
+                last_byte_bits: 8,
+                pause_ms: 1000,
+                data: vec![0xF0],
+            }),
+            Block::DirectRecording {
+                tstates_per_sample: 79,
+                pause_ms: 0,
+                last_byte_bits: 4,
+                data: vec![0xC3],
+            },
+            Block::Pause(0),
+            Block::GroupStart("Demo".to_string()),
+            Block::GroupEnd,
+            Block::Jump(-2),
+            Block::LoopStart(5),
+            Block::LoopEnd,
+            Block::StopIf48K,
+            Block::SetLevel(true),
+            Block::Info("Hi!".to_string()),
+            Block::Info("Archive info".to_string()),
+            Block::Info("Glue".to_string()),
+        ][..]);
+
+        assert_eq!(tape.blocks()[2].describe(), "Pure tone, 10 pulses of 100 T states");
+        assert_eq!(tape.blocks()[6].describe(), "Stop the tape");
+        assert_eq!(tape.blocks()[7].describe(), "Group: Demo");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse_tzx(b"ZXTape!").is_err());
+        assert!(parse_tzx(b"ZXTape!\x1A\x02\x00").is_err());
+        assert!(parse_tzx(&tzx(&[])).unwrap().is_empty());
+        // Unknown block without the length it should start with
+        assert!(parse_tzx(&tzx(&[&[0x20, 0x00, 0x00], &[0x99]])).is_err());
+        // Block cut short
+        assert!(parse_tzx(&tzx(&[&[0x10, 0xF4, 0x01, 0x03, 0x00, 0xFF]])).is_err());
+    }
+
+    #[test]
+    fn test_skipped_blocks() {
+        let tape = parse_tzx(&tzx(&[
+            &[0x20, 0xE8, 0x03],
+            // Select block, emulation info, call sequence and return
+            &[0x28, 0x05, 0x00, 0x01, 0x00, 0x00, 0x01, b'A'],
+            &[0x34, 0, 0, 0, 0, 0, 0, 0, 0],
+            &[0x26, 0x01, 0x00, 0x02, 0x00],
+            &[0x27],
+            // Unknown blocks are skipped by their length
+            &[0x99, 0x03, 0x00, 0x00, 0x00, 0xAA, 0xBB, 0xCC],
+            &[0x12, 0x64, 0x00, 0x0A, 0x00],
+        ])).unwrap();
+
+        assert_eq!(tape.len(), 7);
+        assert_eq!(tape.blocks()[1], Block::Info("Select block".to_string()));
+        assert_eq!(tape.blocks()[2], Block::Info("Emulation info".to_string()));
+        assert_eq!(tape.blocks()[5], Block::Info("Unknown block 0x99".to_string()));
+        assert_eq!(tape.blocks()[6], Block::PureTone { pulse: 100, count: 10 });
+    }
+
+    #[test]
+    fn test_flow_control() {
+        let mut player = TapePlayer::new(Tape::new(vec![
+            Block::PureTone { pulse: 100, count: 2 },
+            Block::LoopStart(3),
+            Block::Pulses(vec![50]),
+            Block::LoopEnd,
+            Block::Jump(2),
+            Block::Pulses(vec![999]),
+            Block::Pause(0),
+            Block::Pulses(vec![10]),
+        ]));
+        player.play();
+        assert_eq!(player.run(1000), vec![(0, true), (100, false), (200, true), (250, false), (300, true)]);
+        assert!(!player.is_playing());
+        assert_eq!(player.block(), 6);
+
+        // Carries on after the stop
+        player.play();
+        assert_eq!(player.run(1000), vec![(0, false)]);
+        assert!(player.is_at_end());
+    }
+
+    #[test]
+    fn test_jump_cycles() {
+        let mut player = TapePlayer::new(Tape::new(vec![
+            Block::Jump(0),
+            Block::Pulses(vec![10]),
+        ]));
+        player.play();
+        assert_eq!(player.run(100), vec![(0, true)]);
+        assert!(player.is_at_end());
+
+        // Nothing to play on the way round, the tape stops
+        let mut player = TapePlayer::new(Tape::new(vec![
+            Block::Pulses(vec![10]),
+            Block::GroupStart("Loop".to_string()),
+            Block::GroupEnd,
+            Block::Jump(-2),
+            Block::Pulses(vec![10]),
+        ]));
+        player.play();
+        assert_eq!(player.run(100), vec![(0, true)]);
+        assert!(!player.is_playing());
+    }
+
+    #[test]
+    fn test_stop_if_48k() {
+        let tape = Tape::new(vec![Block::StopIf48K, Block::Pulses(vec![10])]);
+
+        let mut player = TapePlayer::new(tape.clone());
+        player.play();
+        assert!(player.run(100).is_empty());
+        assert!(!player.is_playing());
+
+        let mut player = TapePlayer::new(tape);
+        player.set_48k(false);
+        player.play();
+        assert_eq!(player.run(100), vec![(0, true)]);
+    }
+
+    #[test]
+    fn test_direct_recording() {
+        let mut player = TapePlayer::new(Tape::new(vec![
+            Block::DirectRecording {
+                tstates_per_sample: 100,
+                pause_ms: 0,
+                last_byte_bits: 8,
+                data: vec![0xC3],
+            },
+            Block::SetLevel(true),
+            Block::Pulses(vec![10, 10]),
+        ]));
+        player.play();
+        // The pulses start from the level set, high like the recording ended
+        assert_eq!(player.run(1000), vec![(0, true), (200, false), (600, true), (800, false), (810, true)]);
+        assert!(player.is_at_end());
+    }
+}
@@ -0,0 +1,114 @@
+extern crate z80emulib;
+
+#[cfg(test)]
+mod test_ulaplus {
+
+    use z80emulib::peripherals::*;
+    use z80emulib::interconnect::*;
+    use z80emulib::framebuffer::*;
+
+    use std::rc::Rc;
+    use std::cell::RefCell;
+
+    fn ula(ulaplus: bool) -> (Interconnect, Rc<RefCell<Ula>>) {
+        let memory = Rc::new(RefCell::new(MemoryBuilder::new().finalize()));
+        let ay = Rc::new(RefCell::new(Ay::new()));
+        let ula = Rc::new(RefCell::new(Ula::new(memory.clone())));
+        if ulaplus {
+            ula.borrow_mut().enable_ulaplus();
+        }
+        (Interconnect::new(memory.clone(), ay, ula.clone()), ula)
+    }
+
+    fn display(ula: &Rc<RefCell<Ula>>) -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(DISPLAY_WIDTH, DISPLAY_HEIGHT);
+        ula.borrow_mut().end_frame();
+        ula.borrow().display(&mut framebuffer);
+        framebuffer
+    }
+
+    #[test]
+    fn test_palette_registers() {
+        let (interconnect, ula) = ula(true);
+
+        interconnect.write_port(ULAPLUS_REGISTER_PORT, 0x05, 0);
+        interconnect.write_port(ULAPLUS_DATA_PORT, 0xE3, 0);
+        assert_eq!(interconnect.read_port(ULAPLUS_DATA_PORT, 0), 0xE3);
+        assert_eq!(ula.borrow().ulaplus().unwrap().get_entry(5), 0xE3);
+
+        // Mode group
+        interconnect.write_port(ULAPLUS_REGISTER_PORT, 0x40, 0);
+        interconnect.write_port(ULAPLUS_DATA_PORT, 0x01, 0);
+        assert_eq!(interconnect.read_port(ULAPLUS_DATA_PORT, 0), 0x01);
+        assert!(ula.borrow().ulaplus().unwrap().palette_mode());
+    }
+
+    #[test]
+    fn test_disabled() {
+        let (interconnect, ula) = ula(false);
+
+        interconnect.write_port(ULAPLUS_REGISTER_PORT, 0x40, 0);
+        interconnect.write_port(ULAPLUS_DATA_PORT, 0x01, 0);
+        // Left floating, and outside the display fetches
+        assert_eq!(interconnect.read_port(ULAPLUS_DATA_PORT, 0), 0xFF);
+        assert!(ula.borrow().ulaplus().is_none());
+    }
+
+    #[test]
+    fn test_grb332() {
+        let mut ulaplus = UlaPlus::new();
+        ulaplus.set_entry(0, 0xFF);
+        ulaplus.set_entry(1, 0b111_000_00);
+        ulaplus.set_entry(2, 0b000_111_00);
+        ulaplus.set_entry(3, 0b000_000_01);
+        ulaplus.set_entry(4, 0b010_100_10);
+        assert_eq!(ulaplus.rgb(0), (255, 255, 255));
+        assert_eq!(ulaplus.rgb(1), (0, 255, 0));
+        assert_eq!(ulaplus.rgb(2), (255, 0, 0));
+        assert_eq!(ulaplus.rgb(3), (0, 0, 109));
+        assert_eq!(ulaplus.rgb(4), (146, 73, 182));
+    }
+
+    #[test]
+    fn test_palette_display() {
+        let (interconnect, ula) = ula(true);
+        // CLUT 3, leftmost pixel set, ink 2 and paper 5
+        interconnect.write_word(0x4000, 0x80, 0);
+        interconnect.write_word(0x5800, 0xEA, 0);
+
+        for &(entry, colour) in [(48 + 2, 0b000_111_00), (48 + 8 + 5, 0b111_000_00),
+                                  (8 + 7, 0b000_000_11)].iter() {
+            interconnect.write_port(ULAPLUS_REGISTER_PORT, entry, 0);
+            interconnect.write_port(ULAPLUS_DATA_PORT, colour, 0);
+        }
+        interconnect.write_port(ULAPLUS_REGISTER_PORT, 0x40, 0);
+        interconnect.write_port(ULAPLUS_DATA_PORT, 0x01, 0);
+
+        let framebuffer = display(&ula);
+        assert_eq!(framebuffer.get_pixel(BORDER_LEFT, BORDER_TOP), (255, 0, 0));
+        assert_eq!(framebuffer.get_pixel(BORDER_LEFT + 1, BORDER_TOP), (0, 255, 0));
+        assert_eq!(framebuffer.get_pixel(0, 0), (0, 0, 255));
+
+        // Back to the usual colours, with FLASH and BRIGHT
+        interconnect.write_port(ULAPLUS_DATA_PORT, 0x00, 0);
+        let framebuffer = display(&ula);
+        assert_eq!(framebuffer.get_pixel(BORDER_LEFT, BORDER_TOP), (255, 0, 0));
+        assert_eq!(framebuffer.get_pixel(BORDER_LEFT + 1, BORDER_TOP), (0, 255, 255));
+        assert_eq!(framebuffer.get_pixel(0, 0), (192, 192, 192));
+    }
+
+    #[test]
+    fn test_restore() {
+        let (interconnect, ula) = ula(true);
+        interconnect.write_port(ULAPLUS_REGISTER_PORT, 0x0F, 0);
+        interconnect.write_port(ULAPLUS_DATA_PORT, 0x1C, 0);
+        interconnect.write_port(ULAPLUS_REGISTER_PORT, 0x40, 0);
+        interconnect.write_port(ULAPLUS_DATA_PORT, 0x01, 0);
+        let saved = ula.borrow().ulaplus().unwrap().clone();
+
+        let (_, restored) = self::ula(false);
+        restored.borrow_mut().set_ulaplus(saved);
+        assert!(restored.borrow().ulaplus().unwrap().palette_mode());
+        assert_eq!(display(&restored).get_pixel(0, 0), (255, 0, 0));
+    }
+}
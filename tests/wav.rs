@@ -0,0 +1,101 @@
+extern crate z80emulib;
+
+#[cfg(test)]
+mod test_wav {
+
+    use z80emulib::wav::*;
+    use z80emulib::machine::*;
+
+    use std::env;
+    use std::fs;
+    use std::io::Cursor;
+
+    fn le_u32(bytes: &[u8]) -> u32 {
+        bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24
+    }
+
+    #[test]
+    fn test_header() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 44100, 1).unwrap();
+        wav.write_samples(&[0, 1, -1]).unwrap();
+        wav.write_samples(&[0x1234]).unwrap();
+        let data = wav.finish().unwrap().into_inner();
+
+        assert_eq!(data.len(), 44 + 8);
+        assert_eq!(&data[0..4], b"RIFF");
+        assert_eq!(le_u32(&data[4..8]), 36 + 8);
+        assert_eq!(&data[8..16], b"WAVEfmt ");
+        assert_eq!(le_u32(&data[16..20]), 16);
+        // PCM, mono, 44100Hz, 88200 bytes per second, 2 bytes per frame,
+        // 16 bits
+        assert_eq!(&data[20..24], &[1, 0, 1, 0]);
+        assert_eq!(le_u32(&data[24..28]), 44100);
+        assert_eq!(le_u32(&data[28..32]), 88200);
+        assert_eq!(&data[32..36], &[2, 0, 16, 0]);
+        assert_eq!(&data[36..40], b"data");
+        assert_eq!(le_u32(&data[40..44]), 8);
+        assert_eq!(&data[44..], &[0, 0, 1, 0, 0xFF, 0xFF, 0x34, 0x12]);
+    }
+
+    #[test]
+    fn test_record_machine() {
+        let mut rom = vec![0; 16 * 1024];
+        // Move the speaker once and stop
+        rom[..6].copy_from_slice(&[
+            0xAF,               // XOR A
+            0xEE, 0x10,         // XOR 10h
+            0xD3, 0xFE,         // OUT (FEh),A
+            0x76]);             // HALT
+        let mut machine = Machine::with_roms(false, Model::Spectrum48K, 48, vec![rom.into_boxed_slice()]);
+
+        let path = env::temp_dir().join("z80emu_test_record.wav");
+        machine.start_audio_recording(&path).unwrap();
+        let mut played = Vec::new();
+        for _ in 0..10 {
+            machine.run_frame();
+            played.extend(machine.audio_samples());
+        }
+        machine.stop_audio_recording().unwrap();
+
+        let data = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        // Left and right
+        assert_eq!(played.len(), 2 * 8805);
+        assert_eq!(&data[22..24], &[2, 0]);
+        assert_eq!(le_u32(&data[40..44]) as usize, played.len() * 2);
+        let recorded: Vec<i16> = data[44..].chunks(2)
+            .map(|b| (b[0] as u16 | (b[1] as u16) << 8) as i16)
+            .collect();
+        assert_eq!(recorded, played);
+        assert_eq!(played[0], 0);
+        assert_eq!(*played.last().unwrap(), (0.96 * 65535.0 / 2.0) as i16);
+    }
+
+    #[test]
+    fn test_record_sample_rate() {
+        let mut rom = vec![0; 16 * 1024];
+        rom[0] = 0x76;          // HALT
+        let mut machine = Machine::with_roms(false, Model::Spectrum48K, 48, vec![rom.into_boxed_slice()]);
+
+        // The window front-end only learns the rate of the sound device
+        // once the recording has started
+        let path = env::temp_dir().join("z80emu_test_record_rate.wav");
+        machine.start_audio_recording(&path).unwrap();
+        machine.set_sample_rate(48000);
+        for _ in 0..10 {
+            machine.run_frame();
+            machine.audio_samples();
+        }
+        machine.stop_audio_recording().unwrap();
+
+        let data = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(le_u32(&data[24..28]), 48000);
+        assert_eq!(le_u32(&data[28..32]), 48000 * 4);
+        // Half a second of sound
+        let frames = le_u32(&data[40..44]) as usize / 4;
+        assert!(frames >= 9580 && frames <= 9590, "{}", frames);
+    }
+}